@@ -1,50 +1,313 @@
+use crate::level::{ENTITY_SIZE, GEN_RANGE};
 use kalosm::language::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tracing::{Instrument, debug, info, info_span, warn};
 
-const DEBUG: bool = false;
+pub const MODEL_NAME: &str = "gpt-4o-mini";
 
 pub mod ai_error;
+pub mod audit_log;
+pub mod consistency;
+pub mod evaluation;
+pub mod few_shot;
+pub mod fidelity;
+pub mod guardrails;
+pub mod judge;
+pub mod offline;
+pub mod prompt_template;
+pub mod rate_limit;
+pub mod tool_gen;
+pub mod usage;
+pub mod validation;
 
 pub use ai_error::AIError;
+pub use audit_log::*;
+pub use consistency::*;
+pub use evaluation::*;
+pub use few_shot::*;
+pub use fidelity::*;
+pub use guardrails::*;
+pub use judge::*;
+pub use offline::*;
+pub use prompt_template::*;
+pub use rate_limit::*;
+pub use tool_gen::*;
+pub use usage::*;
+pub use validation::*;
 
-#[derive(Parse, Clone, Debug, Schema, Deserialize)]
+/// Model provider a request goes to. Each backend has its own rate limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {
+    OpenAI,
+
+    /// Rule based classifier that runs without a network connection
+    Offline,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::OpenAI => "openai",
+            Backend::Offline => "offline",
+        }
+    }
+}
+
+/// How the model turns a prompt into a level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenerationMode {
+    /// Model returns counts and geometry, entities are placed randomly
+    Classify,
+
+    /// Model places each entity itself with tool calls
+    Tools,
+}
+
+#[derive(Parse, Clone, Debug, Default, Schema, Serialize, Deserialize)]
 pub struct LevelGenResponse {
     pub valid: bool,
     pub error: String,
 
     pub square_count: i32,
     pub circle_count: i32,
+
+    #[serde(default)]
+    pub walls: Vec<WallGen>,
+
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleGen>,
+
+    #[serde(default)]
+    pub behaviors: Vec<BehaviorGen>,
+
+    #[serde(default)]
+    pub rules: Option<RulesGen>,
+}
+
+impl LevelGenResponse {
+    /// Response for a prompt that can't become a level
+    pub fn invalid(error: String) -> Self {
+        Self {
+            valid: false,
+            error,
+            ..Default::default()
+        }
+    }
+}
+
+/// Parsed response along with where it came from
+#[derive(Clone, Debug)]
+pub struct LevelGenOutput {
+    pub response: LevelGenResponse,
+    pub raw_response: String,
+    pub model: String,
+
+    /// Prompt template the response came from. Empty when no template was used.
+    pub template_id: String,
+
+    /// Estimated tokens and cost of every call that went into this output
+    pub usage: Usage,
+}
+
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct WallGen {
+    pub start_x: f64,
+    pub start_y: f64,
+    pub end_x: f64,
+    pub end_y: f64,
+}
+
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct ObstacleGen {
+    pub points: Vec<PointGen>,
+}
+
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct PointGen {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// How one team acts during a match
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct BehaviorGen {
+    /// "squares" or "circles"
+    pub team: String,
+
+    /// Checked in order every tick. The first rule whose condition passes is used.
+    pub rules: Vec<BehaviorRuleGen>,
+}
+
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct BehaviorRuleGen {
+    /// "always", "health_below" or "enemy_within"
+    pub condition: String,
+
+    /// health_below is a fraction of max health 0 to 1. enemy_within is a distance.
+    pub condition_value: f64,
+
+    /// "chase", "flee", "patrol", "hold" or "wander"
+    pub action: String,
+
+    /// Target for chase and flee. "nearest_enemy", "nearest_ally" or "center"
+    pub target: String,
+
+    /// Patrol circle radius. Unused by other actions.
+    pub action_value: f64,
+}
+
+/// How a match is won
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct RulesGen {
+    /// "eliminate", "survive" or "kills"
+    pub objective: String,
+
+    /// Team that has to stay alive for "survive". "squares" or "circles"
+    pub team: String,
+
+    /// Kills needed to win for "kills"
+    pub kill_target: i32,
+
+    /// Match length in seconds. 0 for no limit. When time runs out the team with more kills wins.
+    pub time_limit: f64,
+}
+
+/// Everything that shapes the classification prompt
+#[derive(Clone, Debug)]
+pub struct ClassifyConfig {
+    pub template: PromptTemplate,
+    pub examples: ExampleLibrary,
+    pub example_selection: ExampleSelection,
+
+    pub backend: Backend,
+
+    /// Classify offline when the model can't be reached instead of returning the error
+    pub offline_fallback: bool,
+}
+
+impl Default for ClassifyConfig {
+    fn default() -> Self {
+        Self {
+            template: PromptTemplate::default_classify(),
+            examples: ExampleLibrary::builtin(),
+            example_selection: ExampleSelection::TopK(3),
+            backend: Backend::OpenAI,
+            offline_fallback: true,
+        }
+    }
+}
+
+/// seed is the placement seed the response will be used with, recorded in the audit log
+pub async fn classify(
+    prompt: &str,
+    config: &ClassifyConfig,
+    seed: Option<u64>,
+) -> Result<LevelGenOutput, AIError> {
+    if config.backend == Backend::Offline {
+        return Ok(classify_offline(prompt, seed));
+    }
+
+    let mut entry = AuditEntry::start("classify", Backend::OpenAI, MODEL_NAME, prompt);
+    entry.template_id = config.template.id.clone();
+    entry.seed = seed;
+
+    let result = run_classify(prompt, config, &mut entry)
+        .instrument(info_span!("request", kind = "classify", model = MODEL_NAME))
+        .await;
+    entry.finish(result.as_ref().map(|output| &output.response));
+
+    match result {
+        // network and api errors, other errors would fail the same way again
+        Err(error @ AIError::RunningPrompt { .. }) if config.offline_fallback => {
+            warn!(?error, "Model unavailable, classifying offline");
+            Ok(classify_offline(prompt, seed))
+        }
+        result => result,
+    }
 }
 
-pub async fn classify(prompt: &str) -> Result<LevelGenResponse, AIError> {
-    println!("Start classification");
+async fn run_classify(
+    prompt: &str,
+    config: &ClassifyConfig,
+    entry: &mut AuditEntry,
+) -> Result<LevelGenOutput, AIError> {
+    info!("Start classification");
+
+    if let Err(violation) = check_input(prompt) {
+        warn!(?violation, "Prompt blocked by guardrail");
+        entry.model = GUARDRAIL_MODEL.to_string();
+        return Ok(LevelGenOutput {
+            response: LevelGenResponse::invalid(violation.reason()),
+            raw_response: String::new(),
+            model: GUARDRAIL_MODEL.to_string(),
+            template_id: config.template.id.clone(),
+            usage: Usage::default(),
+        });
+    }
 
     let llm = OpenAICompatibleChatModel::builder()
         .with_gpt_4o_mini()
         .build();
 
-    println!("Model started");
+    debug!("Model started");
 
-    let schema: String = LevelGenResponse::schema().to_string();
-    let task =
-        llm.task(&format!("You classify the user's description of a shape. Only include the properties field. Respond in formatted json following this schema {}. ", schema));
+    let examples = config.examples.select(prompt, config.example_selection);
+    let system_prompt = config.template.render(&TemplateVars {
+        schema: LevelGenResponse::schema().to_string(),
+        examples: format_examples(&examples),
+        arena_radius: GEN_RANGE,
+        entity_size: ENTITY_SIZE,
+    })?;
+    let task = llm.task(&system_prompt);
 
-    println!("Running classification");
-    let response_text = task(prompt).await?;
+    debug!("Running classification");
+    let full_prompt = format!("{}{}", system_prompt, prompt);
+    let reservation = check_spending_cap(MODEL_NAME, &full_prompt)?;
+    let task = &task;
+    let mut call = RateLimitedCall::new(Backend::OpenAI, estimate_tokens(&full_prompt));
+    let result = call
+        .run(move || async move { Ok(task(prompt).await?) })
+        .await;
+    entry.retries = call.retries();
+    let response_text = result?;
 
-    let trimmed = response_text
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
+    let usage = record_usage(reservation, "classify", &full_prompt, &response_text);
+    entry.usage = usage;
 
-    if DEBUG {
-        println!("llm response {:?}", trimmed)
+    let trimmed = trim_response(&response_text);
+    entry.raw_response = trimmed.to_string();
+
+    debug!(response = trimmed, "llm response");
+
+    let mut response: LevelGenResponse = {
+        let _parse = info_span!("parse").entered();
+        serde_json::from_str(&trimmed)?
+    };
+    if let Err(violation) = check_output(&response) {
+        warn!(?violation, "Response blocked by guardrail");
+        response = LevelGenResponse::invalid(violation.reason());
     }
+    info!(
+        square_count = response.square_count,
+        circle_count = response.circle_count,
+        "Successful classification"
+    );
 
-    let response: LevelGenResponse = serde_json::from_str(&trimmed)?;
-    println!("Successful classification");
+    Ok(LevelGenOutput {
+        response,
+        raw_response: trimmed.to_string(),
+        model: MODEL_NAME.to_string(),
+        template_id: config.template.id.clone(),
+        usage,
+    })
+}
 
-    Ok(response)
+/// Strip whitespace and markdown code fences the model sometimes wraps json in
+pub fn trim_response(response_text: &str) -> &str {
+    response_text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim()
 }
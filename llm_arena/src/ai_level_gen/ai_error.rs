@@ -1,20 +1,59 @@
+use crate::ai_level_gen::TemplateError;
 use kalosm::language::*;
 
+/// Rate limited and server errors
+const RETRYABLE_STATUS_CODES: [&str; 5] = ["429", "500", "502", "503", "504"];
+
 #[derive(Debug)]
 pub enum AIError {
     /// Error in deserializing the ai response
     ResponseDeserialization { response: String },
 
     /// Kalosm error when running the prompt
-    RunningPrompt,
+    RunningPrompt { message: String },
 
     /// Serde error deserializing the response
     Serde,
+
+    /// Prompt template couldn't be filled in
+    Template,
+
+    /// Session spending is over the cap so no new calls are made
+    SpendingCap { spent: f64, cap: f64 },
+}
+
+impl AIError {
+    /// Rate limited or a server error, worth trying again after a backoff.
+    /// The client only gives us the error text so this looks for the status code in it,
+    /// as a word after "status", "code" or "error" so ids and timings don't match.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AIError::RunningPrompt { message } => {
+                let message = message.to_lowercase();
+                let words: Vec<&str> = message
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                let status_code = words.windows(2).any(|pair| {
+                    matches!(pair[0], "status" | "code" | "error")
+                        && RETRYABLE_STATUS_CODES.contains(&pair[1])
+                });
+
+                status_code
+                    || message.contains("rate limit")
+                    || message.contains("too many requests")
+                    || message.contains("overloaded")
+            }
+            _ => false,
+        }
+    }
 }
 
 impl From<OpenAICompatibleChatModelError> for AIError {
     fn from(err: OpenAICompatibleChatModelError) -> Self {
-        AIError::RunningPrompt
+        AIError::RunningPrompt {
+            message: err.to_string(),
+        }
     }
 }
 
@@ -23,3 +62,50 @@ impl From<serde_json::Error> for AIError {
         AIError::Serde
     }
 }
+
+impl From<TemplateError> for AIError {
+    fn from(err: TemplateError) -> Self {
+        AIError::Template
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retryable(message: &str) -> bool {
+        AIError::RunningPrompt {
+            message: message.to_string(),
+        }
+        .is_retryable()
+    }
+
+    #[test]
+    fn status_codes() {
+        assert!(retryable(
+            "HTTP status client error (429 Too Many Requests) for url"
+        ));
+        assert!(retryable(
+            "HTTP status server error (503 Service Unavailable)"
+        ));
+        assert!(retryable("status 500"));
+        assert!(retryable("error code: 502"));
+        assert!(!retryable("status 400"));
+        assert!(!retryable("error code: 401"));
+    }
+
+    #[test]
+    fn codes_inside_other_numbers() {
+        assert!(!retryable("request req_5004291 failed, invalid api key"));
+        assert!(!retryable("timed out after 4290ms"));
+        assert!(!retryable("context length 8500 exceeded"));
+    }
+
+    #[test]
+    fn messages_without_a_code() {
+        assert!(retryable("Rate limit reached for gpt-4o-mini"));
+        assert!(retryable("the server is overloaded"));
+        assert!(!retryable("invalid json"));
+        assert!(!AIError::Serde.is_retryable());
+    }
+}
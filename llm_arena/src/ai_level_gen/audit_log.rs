@@ -0,0 +1,274 @@
+use crate::ai_level_gen::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+    time::{Instant, SystemTime},
+};
+
+pub const AUDIT_LOG_PATH: &str = "generation_log.jsonl";
+
+/// Where entries are appended. None turns the log off.
+pub static AUDIT_LOG: LazyLock<Mutex<Option<PathBuf>>> =
+    LazyLock::new(|| Mutex::new(Some(PathBuf::from(AUDIT_LOG_PATH))));
+
+/// Environment variables holding keys that must never reach the log
+const SECRET_ENV_VARS: [&str; 2] = ["OPENAI_API_KEY", "ANTHROPIC_API_KEY"];
+
+/// Shortest run of key characters after "sk-" that is treated as a key
+const MIN_KEY_CHARS: usize = 16;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// One model request, written as a line of json
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time in milliseconds when the request started
+    pub timestamp_ms: u64,
+
+    /// What the request was for, like "classify", "judge" or "tools"
+    pub kind: String,
+
+    pub backend: String,
+    pub model: String,
+
+    #[serde(default)]
+    pub template_id: String,
+
+    pub prompt: String,
+    pub raw_response: String,
+
+    /// Response after parsing and guardrails. None when the request failed.
+    #[serde(default)]
+    pub parsed: Option<serde_json::Value>,
+
+    #[serde(default)]
+    pub error: Option<String>,
+
+    pub retries: u32,
+    pub latency_ms: u64,
+
+    /// Placement seed, when the response is placed as a level
+    #[serde(default)]
+    pub seed: Option<u64>,
+
+    #[serde(default)]
+    pub usage: Usage,
+
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+impl AuditEntry {
+    /// Start timing a request
+    pub fn start(kind: &str, backend: Backend, model: &str, prompt: &str) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        Self {
+            timestamp_ms,
+            kind: kind.to_string(),
+            backend: backend.name().to_string(),
+            model: model.to_string(),
+            template_id: String::new(),
+            prompt: prompt.to_string(),
+            raw_response: String::new(),
+            parsed: None,
+            error: None,
+            retries: 0,
+            latency_ms: 0,
+            seed: None,
+            usage: Usage::default(),
+            started: Some(Instant::now()),
+        }
+    }
+
+    /// Fill in the outcome and append the entry to the log
+    pub fn finish<T: Serialize>(mut self, result: Result<&T, &AIError>) {
+        if let Some(started) = self.started {
+            self.latency_ms = started.elapsed().as_millis() as u64;
+        }
+
+        match result {
+            Ok(parsed) => self.parsed = serde_json::to_value(parsed).ok(),
+            Err(error) => self.error = Some(format!("{:?}", error)),
+        }
+
+        append_audit(&self);
+    }
+}
+
+/// Append an entry to the audit log with secrets removed. Failing to log never fails the request.
+pub fn append_audit(entry: &AuditEntry) {
+    let Some(path) = AUDIT_LOG.lock().unwrap().clone() else {
+        return;
+    };
+
+    let mut entry = entry.clone();
+    entry.prompt = redact(&entry.prompt);
+    entry.raw_response = redact(&entry.raw_response);
+    entry.error = entry.error.as_deref().map(redact);
+
+    let line = match serde_json::to_string(&entry) {
+        Ok(line) => redact(&line),
+        Err(error) => {
+            tracing::warn!(?error, "Error serializing audit entry");
+            return;
+        }
+    };
+
+    let file = OpenOptions::new().create(true).append(true).open(&path);
+    match file {
+        Ok(mut file) => {
+            if let Err(error) = writeln!(file, "{}", line) {
+                tracing::warn!(?error, "Error writing audit log");
+            }
+        }
+        Err(error) => tracing::warn!(?error, path = %path.display(), "Error opening audit log"),
+    }
+}
+
+/// Read every entry in a log. Lines that don't parse are skipped and counted.
+pub fn load_audit_log(path: &Path) -> std::io::Result<(Vec<AuditEntry>, usize)> {
+    let file = std::fs::File::open(path)?;
+
+    let mut entries: Vec<AuditEntry> = vec![];
+    let mut skipped = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => skipped += 1,
+        }
+    }
+
+    Ok((entries, skipped))
+}
+
+/// Remove api keys. Catches the values of known key variables and anything shaped like an sk- key.
+pub fn redact(text: &str) -> String {
+    let mut out = text.to_string();
+
+    for var in SECRET_ENV_VARS {
+        if let Ok(key) = std::env::var(var) {
+            if !key.is_empty() {
+                out = out.replace(&key, REDACTED);
+            }
+        }
+    }
+
+    let mut result = String::with_capacity(out.len());
+    let mut rest = out.as_str();
+    while let Some(start) = rest.find("sk-") {
+        let key_len = rest[start + 3..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .count();
+
+        result.push_str(&rest[..start]);
+        if key_len >= MIN_KEY_CHARS {
+            result.push_str(REDACTED);
+        } else {
+            result.push_str(&rest[start..start + 3 + key_len]);
+        }
+        rest = &rest[start + 3 + key_len..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("llm_arena_{}_{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn env_var_key_is_redacted() {
+        let key = "env-secret-value-8c1f";
+        // SAFETY: only this test sets the variable and nothing reads it for anything else
+        unsafe { std::env::set_var("OPENAI_API_KEY", key) };
+
+        let text = redact(&format!("auth failed for key {} on request", key));
+        assert!(!text.contains(key));
+        assert_eq!(text, "auth failed for key [REDACTED] on request");
+    }
+
+    #[test]
+    fn sk_key_is_redacted() {
+        let key = format!("sk-{}", "a1B2_c3-D4".repeat(2));
+        assert!(key.len() - 3 >= MIN_KEY_CHARS);
+
+        let text = redact(&format!("Bearer {}, retry later", key));
+        assert_eq!(text, "Bearer [REDACTED], retry later");
+    }
+
+    #[test]
+    fn short_sk_word_is_left() {
+        let text = "the sk-short flag and risk-free task-list";
+        assert_eq!(redact(text), text);
+    }
+
+    #[test]
+    fn key_in_error_is_redacted() {
+        let path = temp_log("audit_error");
+        let previous = AUDIT_LOG.lock().unwrap().replace(path.clone());
+
+        let key = format!("sk-{}", "x".repeat(MIN_KEY_CHARS));
+        let mut entry = AuditEntry::start("redact_test", Backend::Offline, "model", "prompt");
+        entry.finish::<LevelGenResponse>(Err(&AIError::RunningPrompt {
+            message: format!("invalid api key {}", key),
+        }));
+
+        *AUDIT_LOG.lock().unwrap() = previous;
+
+        let (entries, skipped) = load_audit_log(&path).unwrap();
+        let entry = entries.iter().find(|e| e.kind == "redact_test").unwrap();
+        let error = entry.error.as_ref().unwrap();
+        assert!(!error.contains(&key));
+        assert!(error.contains(REDACTED));
+        assert_eq!(skipped, 0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn unreadable_lines_are_skipped_and_counted() {
+        let path = temp_log("audit_skip");
+        let entry = serde_json::to_string(&AuditEntry::start(
+            "classify",
+            Backend::Offline,
+            "model",
+            "three squares",
+        ))
+        .unwrap();
+        let lines = [
+            entry.as_str(),
+            "",
+            "{not json",
+            r#"{"kind":"missing fields"}"#,
+            entry.as_str(),
+        ];
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let (entries, skipped) = load_audit_log(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(skipped, 2);
+        assert_eq!(entries[0].prompt, "three squares");
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::ai_level_gen::*;
+use futures::future::join_all;
+
+/// Sample counts the UI cycles through. 1 is a single plain classification.
+pub const CONSISTENCY_SAMPLE_OPTIONS: [usize; 4] = [1, 3, 5, 7];
+
+/// How the counts from several samples are combined
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Most common count, ties go to the smaller count
+    MajorityVote,
+
+    /// Middle count, lower middle for an even number of samples
+    Median,
+}
+
+/// How much the samples agreed with the combined response
+#[derive(Clone, Copy, Debug)]
+pub struct Consistency {
+    /// Samples that returned a response
+    pub samples: usize,
+
+    /// Samples with the same validity and counts as the combined response
+    pub agreeing: usize,
+
+    /// Samples that errored and weren't counted
+    pub failed: usize,
+}
+
+impl Consistency {
+    /// Agreement ratio 0 to 1, used as the confidence of the combined response
+    pub fn ratio(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.agreeing as f64 / self.samples as f64
+    }
+}
+
+/// Run classify several times at once and combine the responses.
+/// Fails only when every sample fails.
+pub async fn classify_consistent(
+    prompt: &str,
+    config: &ClassifyConfig,
+    seed: Option<u64>,
+    samples: usize,
+    aggregation: Aggregation,
+) -> Result<(LevelGenOutput, Consistency), AIError> {
+    let results = join_all((0..samples.max(1)).map(|_| classify(prompt, config, seed))).await;
+
+    let mut outputs: Vec<LevelGenOutput> = vec![];
+    let mut first_error: Option<AIError> = None;
+    for result in results {
+        match result {
+            Ok(output) => outputs.push(output),
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    if outputs.is_empty() {
+        return Err(first_error.unwrap_or(AIError::RunningPrompt {
+            message: "No samples were run".to_string(),
+        }));
+    }
+
+    let (output, agreeing) = combine_outputs(&outputs, aggregation);
+
+    tracing::info!(samples = outputs.len(), agreeing, "Combined samples");
+
+    Ok((
+        output,
+        Consistency {
+            samples: outputs.len(),
+            agreeing,
+            failed: samples.max(1) - outputs.len(),
+        },
+    ))
+}
+
+/// Combine the samples into one output. Returns it with the number of samples that agree.
+/// outputs can't be empty.
+fn combine_outputs(
+    outputs: &[LevelGenOutput],
+    aggregation: Aggregation,
+) -> (LevelGenOutput, usize) {
+    let valid = outputs.iter().filter(|o| o.response.valid).count() * 2 > outputs.len();
+
+    // the counts of the outvoted samples don't count, an invalid response has no counts to speak of
+    let majority: Vec<&LevelGenOutput> = outputs
+        .iter()
+        .filter(|o| o.response.valid == valid)
+        .collect();
+    let square_count = combine(
+        majority.iter().map(|o| o.response.square_count).collect(),
+        aggregation,
+    );
+    let circle_count = combine(
+        majority.iter().map(|o| o.response.circle_count).collect(),
+        aggregation,
+    );
+
+    let agrees = |o: &&LevelGenOutput| {
+        o.response.valid == valid
+            && o.response.square_count == square_count
+            && o.response.circle_count == circle_count
+    };
+    let agreeing = outputs.iter().filter(agrees).count();
+
+    // walls, behaviors and rules can't be averaged so they come from an agreeing sample
+    let mut output = outputs.iter().find(agrees).unwrap_or(majority[0]).clone();
+    output.response.valid = valid;
+    output.response.square_count = square_count;
+    output.response.circle_count = circle_count;
+
+    // saved levels and the audit reload from the raw response, it has to hold the combined counts
+    output.raw_response = serde_json::to_string(&output.response).unwrap();
+
+    output.usage = Usage::default();
+    for sample in outputs {
+        output.usage.add(&sample.usage);
+    }
+
+    (output, agreeing)
+}
+
+fn combine(mut counts: Vec<i32>, aggregation: Aggregation) -> i32 {
+    counts.sort();
+
+    match aggregation {
+        Aggregation::Median => counts[(counts.len() - 1) / 2],
+        Aggregation::MajorityVote => {
+            let mut best = counts[0];
+            let mut best_votes = 0;
+            for count in &counts {
+                let votes = counts.iter().filter(|c| *c == count).count();
+                // sorted, so the first count to reach the top wins ties
+                if votes > best_votes {
+                    best = *count;
+                    best_votes = votes;
+                }
+            }
+            best
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(valid: bool, squares: i32, circles: i32) -> LevelGenOutput {
+        LevelGenOutput {
+            response: LevelGenResponse {
+                valid,
+                error: if valid {
+                    String::new()
+                } else {
+                    "not a level".to_string()
+                },
+                square_count: squares,
+                circle_count: circles,
+                ..Default::default()
+            },
+            raw_response: String::new(),
+            model: "test".to_string(),
+            template_id: String::new(),
+            usage: Usage {
+                prompt_tokens: 10,
+                completion_tokens: 5,
+                cost: 0.5,
+            },
+        }
+    }
+
+    #[test]
+    fn majority_vote() {
+        assert_eq!(combine(vec![3, 5, 3], Aggregation::MajorityVote), 3);
+        assert_eq!(combine(vec![7], Aggregation::MajorityVote), 7);
+    }
+
+    #[test]
+    fn majority_vote_ties_go_to_the_smaller_count() {
+        assert_eq!(combine(vec![8, 4, 8, 4], Aggregation::MajorityVote), 4);
+        assert_eq!(combine(vec![9, 2, 6], Aggregation::MajorityVote), 2);
+    }
+
+    #[test]
+    fn median() {
+        assert_eq!(combine(vec![9, 1, 5], Aggregation::Median), 5);
+        assert_eq!(combine(vec![2, 100, 3, 4, 1], Aggregation::Median), 3);
+    }
+
+    #[test]
+    fn even_median_is_the_lower_middle() {
+        assert_eq!(combine(vec![10, 2, 6, 4], Aggregation::Median), 4);
+        assert_eq!(combine(vec![5, 3], Aggregation::Median), 3);
+    }
+
+    #[test]
+    fn combined_output() {
+        let outputs = [sample(true, 4, 6), sample(true, 4, 2), sample(true, 4, 6)];
+        let (output, agreeing) = combine_outputs(&outputs, Aggregation::MajorityVote);
+
+        assert!(output.response.valid);
+        assert_eq!(output.response.square_count, 4);
+        assert_eq!(output.response.circle_count, 6);
+        assert_eq!(agreeing, 2);
+        assert_eq!(output.usage.prompt_tokens, 30);
+        assert_eq!(output.usage.cost, 1.5);
+
+        let raw: LevelGenResponse = serde_json::from_str(&output.raw_response).unwrap();
+        assert_eq!(raw.circle_count, 6);
+    }
+
+    #[test]
+    fn invalid_samples_dont_drag_down_a_valid_majority() {
+        // without the filter the zero counts of the invalid sample would win the median
+        let outputs = [
+            sample(true, 5, 5),
+            sample(false, 0, 0),
+            sample(true, 7, 9),
+            sample(false, 0, 0),
+            sample(true, 6, 8),
+        ];
+        let (output, agreeing) = combine_outputs(&outputs, Aggregation::Median);
+
+        assert!(output.response.valid);
+        assert_eq!(output.response.square_count, 6);
+        assert_eq!(output.response.circle_count, 8);
+        assert_eq!(agreeing, 1);
+    }
+
+    #[test]
+    fn invalid_majority() {
+        let outputs = [sample(false, 0, 0), sample(true, 3, 3), sample(false, 0, 0)];
+        let (output, agreeing) = combine_outputs(&outputs, Aggregation::MajorityVote);
+
+        assert!(!output.response.valid);
+        assert_eq!(output.response.error, "not a level");
+        assert_eq!(output.response.square_count, 0);
+        assert_eq!(agreeing, 2);
+    }
+
+    #[test]
+    fn even_split_is_invalid() {
+        let outputs = [sample(true, 3, 3), sample(false, 0, 0)];
+        let (output, agreeing) = combine_outputs(&outputs, Aggregation::Median);
+
+        assert!(!output.response.valid);
+        assert_eq!(agreeing, 1);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{ai_level_gen::*, level::*, rng::*};
+use std::path::Path;
+
+/// Prompts to evaluate, one per line. Blank lines and lines starting with # are skipped.
+pub fn load_eval_prompts(path: &Path) -> std::io::Result<Vec<String>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
+}
+
+#[derive(Clone, Debug)]
+pub struct EvalCase {
+    pub prompt: String,
+
+    /// None when classify failed
+    pub fidelity: Option<FidelityReport>,
+    pub valid: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct EvalReport {
+    pub template_id: String,
+    pub cases: Vec<EvalCase>,
+}
+
+impl EvalReport {
+    pub fn errors(&self) -> usize {
+        self.cases.iter().filter(|c| c.fidelity.is_none()).count()
+    }
+
+    pub fn invalid(&self) -> usize {
+        self.cases
+            .iter()
+            .filter(|c| c.fidelity.is_some() && !c.valid)
+            .count()
+    }
+
+    /// Prompts where every expectation was met
+    pub fn prompts_passed(&self) -> usize {
+        self.cases
+            .iter()
+            .filter(|c| c.fidelity.as_ref().map(|f| f.passed()).unwrap_or(false))
+            .count()
+    }
+
+    /// Expectations met and total, across all prompts. Failed prompts count every expectation as missed.
+    pub fn expectations(&self) -> (usize, usize) {
+        let mut met = 0;
+        let mut total = 0;
+        for case in &self.cases {
+            match &case.fidelity {
+                Some(fidelity) => {
+                    met += fidelity.pass_count();
+                    total += fidelity.checks.len();
+                }
+                None => total += extract_expectations(&case.prompt).len(),
+            }
+        }
+        (met, total)
+    }
+
+    pub fn summary(&self) -> String {
+        let (met, total) = self.expectations();
+        format!(
+            "{}: {}/{} prompts passed, {}/{} expectations met, {} errors, {} invalid",
+            self.template_id,
+            self.prompts_passed(),
+            self.cases.len(),
+            met,
+            total,
+            self.errors(),
+            self.invalid()
+        )
+    }
+}
+
+/// Classify every prompt with the config and check the results against the prompts.
+/// Levels are placed from seed so two templates are compared on the same placement.
+pub async fn evaluate_template(
+    prompts: &[String],
+    config: &ClassifyConfig,
+    seed: u64,
+) -> EvalReport {
+    let mut cases: Vec<EvalCase> = vec![];
+
+    for (i, prompt) in prompts.iter().enumerate() {
+        let case_seed = seed.wrapping_add(i as u64);
+        let case = match classify(prompt, config, Some(case_seed)).await {
+            Ok(mut output) => {
+                let validation = validate(&mut output.response, &ValidationLimits::default());
+
+                let mut rng = Rng::new(case_seed);
+                let level = Level::from_response(&output.response, &mut || rng.next_f64());
+                let placed = if validation.rejected {
+                    None
+                } else {
+                    Some(&level)
+                };
+
+                EvalCase {
+                    prompt: prompt.clone(),
+                    fidelity: Some(check_fidelity(prompt, &output.response, placed)),
+                    valid: output.response.valid,
+                }
+            }
+            Err(error) => {
+                tracing::warn!(prompt, ?error, "Eval prompt failed");
+                EvalCase {
+                    prompt: prompt.clone(),
+                    fidelity: None,
+                    valid: false,
+                }
+            }
+        };
+
+        cases.push(case);
+    }
+
+    EvalReport {
+        template_id: config.template.id.clone(),
+        cases,
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    ai_level_gen::*,
+    level::{SaveError, prompt_hash},
+    rng::*,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const BUILTIN_EXAMPLES: &str = include_str!("../../resources/prompts/examples.json");
+
+/// A prompt and the response we want the model to give for it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FewShotExample {
+    pub prompt: String,
+    pub response: LevelGenResponse,
+}
+
+/// Which examples go into the classification prompt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExampleSelection {
+    None,
+    All,
+
+    /// The k examples with the most words in common with the prompt
+    TopK(usize),
+
+    /// k examples picked at random. Seeded from the prompt so a prompt always gets the same examples.
+    Random(usize),
+}
+
+impl ExampleSelection {
+    pub fn label(&self) -> String {
+        match self {
+            ExampleSelection::None => "Examples: None".to_string(),
+            ExampleSelection::All => "Examples: All".to_string(),
+            ExampleSelection::TopK(k) => format!("Examples: Top {}", k),
+            ExampleSelection::Random(k) => format!("Examples: Random {}", k),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExampleLibrary {
+    pub examples: Vec<FewShotExample>,
+}
+
+impl ExampleLibrary {
+    /// Curated examples shipped with the game
+    pub fn builtin() -> Self {
+        Self {
+            examples: serde_json::from_str(BUILTIN_EXAMPLES)
+                .expect("Builtin few shot examples don't parse"),
+        }
+    }
+
+    /// Load a json list of examples
+    pub fn load(path: &Path) -> Result<Self, SaveError> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(Self {
+            examples: serde_json::from_str(&data)?,
+        })
+    }
+
+    pub fn select(&self, prompt: &str, selection: ExampleSelection) -> Vec<&FewShotExample> {
+        match selection {
+            ExampleSelection::None => vec![],
+            ExampleSelection::All => self.examples.iter().collect(),
+            ExampleSelection::TopK(k) => {
+                let words = word_set(prompt);
+                let mut scored: Vec<(f64, &FewShotExample)> = self
+                    .examples
+                    .iter()
+                    .map(|e| (similarity(&words, &word_set(&e.prompt)), e))
+                    .collect();
+
+                // stable sort keeps library order for ties
+                scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+                scored.into_iter().take(k).map(|(_, e)| e).collect()
+            }
+            ExampleSelection::Random(k) => {
+                let mut rng = Rng::new(prompt_hash(prompt) as u64);
+                let mut pool: Vec<&FewShotExample> = self.examples.iter().collect();
+                let mut picked: Vec<&FewShotExample> = vec![];
+
+                while picked.len() < k && !pool.is_empty() {
+                    let i = (rng.next_u64() % pool.len() as u64) as usize;
+                    picked.push(pool.swap_remove(i));
+                }
+                picked
+            }
+        }
+    }
+}
+
+/// Text for the {{examples}} placeholder
+pub fn format_examples(examples: &[&FewShotExample]) -> String {
+    if examples.is_empty() {
+        return String::new();
+    }
+
+    let mut out = "Here are examples of descriptions and the correct response. ".to_string();
+    for example in examples {
+        out.push_str(&format!(
+            "Description: {} Response: {} ",
+            example.prompt,
+            serde_json::to_string(&example.response).unwrap()
+        ));
+    }
+    out
+}
+
+fn word_set(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+/// Jaccard similarity of two sorted word sets
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    let shared = a.iter().filter(|w| b.binary_search(w).is_ok()).count();
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        return 0.0;
+    }
+    shared as f64 / union as f64
+}
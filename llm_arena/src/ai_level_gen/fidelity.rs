@@ -0,0 +1,606 @@
+use crate::{ai_level_gen::*, level::Level};
+
+/// Something in the level that can be counted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subject {
+    Squares,
+    Circles,
+    Walls,
+    Obstacles,
+}
+
+impl Subject {
+    pub fn from_word(word: &str) -> Option<Subject> {
+        match word {
+            "square" | "squares" => Some(Subject::Squares),
+            "circle" | "circles" => Some(Subject::Circles),
+            "wall" | "walls" => Some(Subject::Walls),
+            "obstacle" | "obstacles" => Some(Subject::Obstacles),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Subject::Squares => "squares",
+            Subject::Circles => "circles",
+            Subject::Walls => "walls",
+            Subject::Obstacles => "obstacles",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Exactly,
+    AtLeast,
+    AtMost,
+}
+
+/// A fact stated in the prompt that can be checked against the level
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expectation {
+    Count {
+        subject: Subject,
+        comparison: Comparison,
+        value: i32,
+    },
+
+    /// There are more of the first than the second
+    MoreThan(Subject, Subject),
+
+    SameCount(Subject, Subject),
+}
+
+impl Expectation {
+    pub fn holds(&self, counts: &Counts) -> bool {
+        match self {
+            Expectation::Count {
+                subject,
+                comparison,
+                value,
+            } => {
+                let count = counts.get(*subject);
+                match comparison {
+                    Comparison::Exactly => count == *value,
+                    Comparison::AtLeast => count >= *value,
+                    Comparison::AtMost => count <= *value,
+                }
+            }
+            Expectation::MoreThan(more, fewer) => counts.get(*more) > counts.get(*fewer),
+            Expectation::SameCount(a, b) => counts.get(*a) == counts.get(*b),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Expectation::Count {
+                subject,
+                comparison,
+                value,
+            } => match comparison {
+                Comparison::Exactly => format!("{} {}", value, subject.name()),
+                Comparison::AtLeast => format!("at least {} {}", value, subject.name()),
+                Comparison::AtMost => format!("at most {} {}", value, subject.name()),
+            },
+            Expectation::MoreThan(more, fewer) => {
+                format!("more {} than {}", more.name(), fewer.name())
+            }
+            Expectation::SameCount(a, b) => {
+                format!("same number of {} and {}", a.name(), b.name())
+            }
+        }
+    }
+
+    fn subjects(&self) -> Vec<Subject> {
+        match self {
+            Expectation::Count { subject, .. } => vec![*subject],
+            Expectation::MoreThan(a, b) | Expectation::SameCount(a, b) => vec![*a, *b],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counts {
+    pub squares: i32,
+    pub circles: i32,
+    pub walls: i32,
+    pub obstacles: i32,
+}
+
+impl Counts {
+    pub fn from_response(resp: &LevelGenResponse) -> Self {
+        Self {
+            squares: resp.square_count,
+            circles: resp.circle_count,
+            walls: resp.walls.len() as i32,
+            obstacles: resp.obstacles.len() as i32,
+        }
+    }
+
+    pub fn from_level(level: &Level) -> Self {
+        Self {
+            squares: level.squares.len() as i32,
+            circles: level.circles.len() as i32,
+            walls: level.walls.len() as i32,
+            obstacles: level.obstacles.len() as i32,
+        }
+    }
+
+    pub fn get(&self, subject: Subject) -> i32 {
+        match subject {
+            Subject::Squares => self.squares,
+            Subject::Circles => self.circles,
+            Subject::Walls => self.walls,
+            Subject::Obstacles => self.obstacles,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FidelityCheck {
+    pub expectation: Expectation,
+
+    /// Does the model response meet the expectation
+    pub response: bool,
+
+    /// Do the placed entities meet the expectation. None when nothing was placed.
+    pub placed: Option<bool>,
+
+    /// Actual counts, for showing why a check failed
+    pub detail: String,
+}
+
+impl FidelityCheck {
+    pub fn passed(&self) -> bool {
+        self.response && self.placed.unwrap_or(true)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FidelityReport {
+    pub checks: Vec<FidelityCheck>,
+}
+
+impl FidelityReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed())
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.checks.iter().filter(|c| c.passed()).count()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "fidelity {}/{} expectations met",
+            self.pass_count(),
+            self.checks.len()
+        )
+    }
+}
+
+/// Check the facts stated in the prompt against the response and, when given, the placed level
+pub fn check_fidelity(
+    prompt: &str,
+    resp: &LevelGenResponse,
+    level: Option<&Level>,
+) -> FidelityReport {
+    let response_counts = Counts::from_response(resp);
+    let placed_counts = level.map(Counts::from_level);
+
+    let checks = extract_expectations(prompt)
+        .into_iter()
+        .map(|expectation| {
+            let detail = expectation
+                .subjects()
+                .iter()
+                .map(|subject| {
+                    let response = response_counts.get(*subject);
+                    match placed_counts {
+                        Some(placed) if placed.get(*subject) != response => format!(
+                            "{} {} ({} placed)",
+                            response,
+                            subject.name(),
+                            placed.get(*subject)
+                        ),
+                        _ => format!("{} {}", response, subject.name()),
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            FidelityCheck {
+                response: expectation.holds(&response_counts),
+                placed: placed_counts.map(|c| expectation.holds(&c)),
+                expectation,
+                detail,
+            }
+        })
+        .collect();
+
+    FidelityReport { checks }
+}
+
+/// Pull checkable facts out of the prompt. Only simple phrasings are understood,
+/// anything else is left for the judge.
+pub fn extract_expectations(prompt: &str) -> Vec<Expectation> {
+    let lower = prompt.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let word = |i: usize| words.get(i).copied().unwrap_or("");
+    let subject = |i: usize| Subject::from_word(word(i));
+
+    let mut expectations: Vec<Expectation> = vec![];
+
+    for i in 0..words.len() {
+        // "more squares than circles", "fewer circles than squares"
+        if let (Some(a), "than", Some(b)) = (subject(i + 1), word(i + 2), subject(i + 3)) {
+            match word(i) {
+                "more" => expectations.push(Expectation::MoreThan(a, b)),
+                "fewer" | "less" => expectations.push(Expectation::MoreThan(b, a)),
+                _ => {}
+            }
+        }
+
+        // "squares outnumber circles"
+        if let (Some(a), "outnumber", Some(b)) = (subject(i), word(i + 1), subject(i + 2)) {
+            expectations.push(Expectation::MoreThan(a, b));
+        }
+
+        // "as many squares as circles"
+        if let ("as", "many", Some(a), "as", Some(b)) = (
+            word(i),
+            word(i + 1),
+            subject(i + 2),
+            word(i + 3),
+            subject(i + 4),
+        ) {
+            expectations.push(Expectation::SameCount(a, b));
+        }
+
+        // "same number of squares and circles", "equal amount of squares as circles"
+        if matches!(word(i), "same" | "equal")
+            && matches!(word(i + 1), "number" | "numbers" | "amount")
+            && word(i + 2) == "of"
+            && matches!(word(i + 4), "and" | "as")
+        {
+            if let (Some(a), Some(b)) = (subject(i + 3), subject(i + 5)) {
+                expectations.push(Expectation::SameCount(a, b));
+            }
+        }
+
+        // counts written before a subject, "10 squares", "at least five red circles", "no walls"
+        let Some(counted) = subject(i) else {
+            continue;
+        };
+
+        if let Some((value, start)) = number_before(&words, i) {
+            let (comparison, value) =
+                match (word(start.wrapping_sub(2)), word(start.wrapping_sub(1))) {
+                    ("at", "least") => (Comparison::AtLeast, value),
+                    ("at", "most") | ("up", "to") => (Comparison::AtMost, value),
+                    ("more", "than") if word(start.wrapping_sub(3)) == "no" => {
+                        (Comparison::AtMost, value)
+                    }
+                    ("more", "than") => (Comparison::AtLeast, value.saturating_add(1)),
+                    // "fewer than 0 squares" can't be met, leave it for the judge
+                    ("fewer", "than") | ("less", "than") | (_, "under") if value <= 0 => continue,
+                    ("fewer", "than") | ("less", "than") => (Comparison::AtMost, value - 1),
+                    (_, "over") => (Comparison::AtLeast, value.saturating_add(1)),
+                    (_, "under") => (Comparison::AtMost, value - 1),
+                    _ => (Comparison::Exactly, value),
+                };
+
+            expectations.push(Expectation::Count {
+                subject: counted,
+                comparison,
+                value: value.max(0),
+            });
+        } else if matches!(word(i.wrapping_sub(1)), "no" | "without")
+            || (word(i.wrapping_sub(2)) == "without" && word(i - 1) == "any")
+        {
+            expectations.push(Expectation::Count {
+                subject: counted,
+                comparison: Comparison::Exactly,
+                value: 0,
+            });
+        }
+    }
+
+    // the same fact can be matched by more than one phrasing
+    let mut unique: Vec<Expectation> = vec![];
+    for expectation in expectations {
+        if !unique.contains(&expectation) {
+            unique.push(expectation);
+        }
+    }
+    unique
+}
+
+/// Find a number in the few words before the subject at index end.
+/// Returns the value and the index of the first word of the number.
+fn number_before(words: &[&str], end: usize) -> Option<(i32, usize)> {
+    // skip a couple of adjectives, "five big red squares"
+    for back in 1..=3 {
+        let Some(i) = end.checked_sub(back) else {
+            return None;
+        };
+
+        let word = words[i];
+        if Subject::from_word(word).is_some() || matches!(word, "and" | "than" | "or" | "vs") {
+            return None;
+        }
+
+        if number_word(word).is_some() {
+            return number_ending_at(words, i);
+        }
+    }
+
+    None
+}
+
+/// Read a number written over several words that ends at index end, "twenty five",
+/// "two dozen", "two hundred fifty". Returns the value and the index of the first word.
+fn number_ending_at(words: &[&str], end: usize) -> Option<(i32, usize)> {
+    let value = number_word(words[end])?;
+    let before = |i: usize| i.checked_sub(1).and_then(|p| number_ending_at(words, p));
+
+    // multipliers scale the number before them, "two dozen". Alone they count once, "a dozen".
+    if matches!(words[end], "dozen" | "hundred") {
+        return match before(end).filter(|(count, _)| *count > 0 && *count < 100) {
+            Some((count, start)) => Some((count * value, start)),
+            None => Some((value, end)),
+        };
+    }
+
+    let mut value = value;
+    let mut start = end;
+
+    // "twenty five"
+    if value < 10 && start > 0 {
+        if let Some(tens) =
+            number_word(words[start - 1]).filter(|t| *t >= 20 && *t < 100 && t % 10 == 0)
+        {
+            value += tens;
+            start -= 1;
+        }
+    }
+
+    // "two hundred fifty"
+    if value < 100 && start > 0 && words[start - 1] == "hundred" {
+        if let Some((hundreds, first)) = number_ending_at(words, start - 1) {
+            value += hundreds;
+            start = first;
+        }
+    }
+
+    Some((value, start))
+}
+
+fn number_word(word: &str) -> Option<i32> {
+    if let Ok(value) = word.parse::<i32>() {
+        return Some(value);
+    }
+
+    let value = match word {
+        "zero" => 0,
+        "one" | "single" => 1,
+        "two" | "pair" => 2,
+        "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "six" => 6,
+        "seven" => 7,
+        "eight" => 8,
+        "nine" => 9,
+        "ten" => 10,
+        "eleven" => 11,
+        "twelve" | "dozen" => 12,
+        "thirteen" => 13,
+        "fourteen" => 14,
+        "fifteen" => 15,
+        "sixteen" => 16,
+        "seventeen" => 17,
+        "eighteen" => 18,
+        "nineteen" => 19,
+        "twenty" => 20,
+        "thirty" => 30,
+        "forty" => 40,
+        "fifty" => 50,
+        "hundred" => 100,
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Entity;
+    use elara_engine::vectors::VecTwo;
+
+    fn count(subject: Subject, comparison: Comparison, value: i32) -> Expectation {
+        Expectation::Count {
+            subject,
+            comparison,
+            value,
+        }
+    }
+
+    fn exactly(subject: Subject, value: i32) -> Expectation {
+        count(subject, Comparison::Exactly, value)
+    }
+
+    fn number(text: &str) -> Option<(i32, usize)> {
+        let words: Vec<&str> = text.split(' ').collect();
+        number_before(&words, words.len() - 1)
+    }
+
+    #[test]
+    fn number_words() {
+        assert_eq!(number("three squares"), Some((3, 0)));
+        assert_eq!(number("a dozen squares"), Some((12, 1)));
+        assert_eq!(number("a single square"), Some((1, 1)));
+        assert_eq!(number("twenty five squares"), Some((25, 0)));
+        assert_eq!(number("40 squares"), Some((40, 0)));
+    }
+
+    #[test]
+    fn multipliers() {
+        assert_eq!(number("two hundred squares"), Some((200, 0)));
+        assert_eq!(number("two dozen squares"), Some((24, 0)));
+        assert_eq!(number("a hundred squares"), Some((100, 1)));
+        assert_eq!(number("two hundred fifty squares"), Some((250, 0)));
+        assert_eq!(number("three hundred twenty five squares"), Some((325, 0)));
+        assert_eq!(number("3 dozen squares"), Some((36, 0)));
+    }
+
+    #[test]
+    fn numbers_past_adjectives() {
+        assert_eq!(number("five big red squares"), Some((5, 0)));
+        assert_eq!(number("five very big shiny squares"), None);
+        assert_eq!(number("circles and squares"), None);
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(
+            extract_expectations("Two hundred squares against two dozen circles"),
+            vec![
+                exactly(Subject::Squares, 200),
+                exactly(Subject::Circles, 24)
+            ]
+        );
+        assert_eq!(
+            extract_expectations("twenty five circles and 3 walls"),
+            vec![exactly(Subject::Circles, 25), exactly(Subject::Walls, 3)]
+        );
+    }
+
+    #[test]
+    fn none_of_a_subject() {
+        assert_eq!(
+            extract_expectations("no walls, without any obstacles, squares without circles"),
+            vec![
+                exactly(Subject::Walls, 0),
+                exactly(Subject::Obstacles, 0),
+                exactly(Subject::Circles, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn bounds() {
+        let cases = [
+            ("at least 3 squares", Comparison::AtLeast, 3),
+            ("at most 4 squares", Comparison::AtMost, 4),
+            ("up to 6 squares", Comparison::AtMost, 6),
+            ("more than 5 squares", Comparison::AtLeast, 6),
+            ("no more than 8 squares", Comparison::AtMost, 8),
+            ("fewer than 5 squares", Comparison::AtMost, 4),
+            ("less than 1 squares", Comparison::AtMost, 0),
+            ("over 10 squares", Comparison::AtLeast, 11),
+            ("under 3 squares", Comparison::AtMost, 2),
+            (
+                "more than 2147483647 squares",
+                Comparison::AtLeast,
+                i32::MAX,
+            ),
+            ("over 2147483647 squares", Comparison::AtLeast, i32::MAX),
+        ];
+        for (prompt, comparison, value) in cases {
+            assert_eq!(
+                extract_expectations(prompt),
+                vec![count(Subject::Squares, comparison, value)],
+                "{}",
+                prompt
+            );
+        }
+    }
+
+    #[test]
+    fn impossible_bounds_are_dropped() {
+        assert!(extract_expectations("fewer than 0 squares").is_empty());
+        assert!(extract_expectations("less than zero circles").is_empty());
+        assert!(extract_expectations("under 0 walls").is_empty());
+    }
+
+    #[test]
+    fn numbers_too_big_for_a_count() {
+        assert!(extract_expectations("99999999999 squares").is_empty());
+    }
+
+    #[test]
+    fn relations() {
+        let more = Expectation::MoreThan(Subject::Squares, Subject::Circles);
+        assert_eq!(
+            extract_expectations("more squares than circles"),
+            vec![more.clone()]
+        );
+        assert_eq!(
+            extract_expectations("fewer circles than squares"),
+            vec![more.clone()]
+        );
+        assert_eq!(
+            extract_expectations("squares outnumber circles"),
+            vec![more]
+        );
+
+        let same = Expectation::SameCount(Subject::Squares, Subject::Circles);
+        assert_eq!(
+            extract_expectations("as many squares as circles"),
+            vec![same.clone()]
+        );
+        assert_eq!(
+            extract_expectations("same number of squares and circles"),
+            vec![same]
+        );
+    }
+
+    #[test]
+    fn repeated_facts_are_kept_once() {
+        assert_eq!(
+            extract_expectations("5 squares. Remember, 5 squares"),
+            vec![exactly(Subject::Squares, 5)]
+        );
+    }
+
+    #[test]
+    fn unrelated_prompt_has_no_expectations() {
+        assert!(extract_expectations("a castle on a hill").is_empty());
+    }
+
+    #[test]
+    fn fidelity_against_response_and_level() {
+        let response = LevelGenResponse {
+            valid: true,
+            square_count: 3,
+            circle_count: 12,
+            ..Default::default()
+        };
+
+        let report = check_fidelity("three squares and a dozen circles", &response, None);
+        assert!(report.passed());
+        assert_eq!(report.pass_count(), 2);
+
+        // placement only fit 10 circles
+        let mut level = Level::new();
+        for _ in 0..3 {
+            level.squares.push(Entity::new(VecTwo::new(0.0, 0.0)));
+        }
+        for _ in 0..10 {
+            level.circles.push(Entity::new(VecTwo::new(0.0, 0.0)));
+        }
+
+        let report = check_fidelity("three squares and a dozen circles", &response, Some(&level));
+        assert!(!report.passed());
+        let failed = report.checks.iter().find(|c| !c.passed()).unwrap();
+        assert!(failed.response);
+        assert_eq!(failed.placed, Some(false));
+        assert_eq!(failed.detail, "12 circles (10 placed)");
+    }
+}
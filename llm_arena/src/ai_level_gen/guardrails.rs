@@ -0,0 +1,198 @@
+use crate::ai_level_gen::*;
+
+/// Longest prompt sent to the model
+pub const MAX_PROMPT_CHARS: usize = 500;
+
+/// Longest error message accepted from the model
+const MAX_ERROR_CHARS: usize = 200;
+
+/// Longest value accepted for the enum-like string fields, like a behavior action
+const MAX_FIELD_CHARS: usize = 32;
+
+/// Model name recorded when a guardrail answered instead of the model
+pub const GUARDRAIL_MODEL: &str = "guardrail";
+
+/// Phrases used to talk the model out of its instructions
+const INJECTION_PHRASES: [&str; 14] = [
+    "ignore previous",
+    "ignore all previous",
+    "ignore the above",
+    "ignore your instructions",
+    "disregard previous",
+    "disregard the above",
+    "disregard your instructions",
+    "forget your instructions",
+    "new instructions",
+    "system prompt",
+    "you are now",
+    "pretend you are",
+    "set valid to true",
+    "output the following",
+];
+
+/// Words that mark an abusive request
+const ABUSIVE_WORDS: [&str; 4] = ["kys", "nazi", "rape", "slur"];
+
+/// Words that are abusive on their own but show up in harmless battle prompts,
+/// "suicide squad of circles". Only rejected when the prompt isn't about the arena,
+/// so a hostile prompt that also mentions squares gets through.
+const ABUSIVE_OFF_ARENA_WORDS: [&str; 2] = ["genocide", "suicide"];
+
+/// Openers for requests that have nothing to do with building a level
+const OFF_TOPIC_PHRASES: [&str; 12] = [
+    "write a",
+    "write me",
+    "tell me",
+    "explain",
+    "translate",
+    "what is",
+    "who is",
+    "how do i",
+    "how to",
+    "recipe",
+    "poem",
+    "essay",
+];
+
+/// Words that show the prompt is about the arena, even if it starts like an off topic request
+const ARENA_WORDS: [&str; 14] = [
+    "square", "circle", "team", "arena", "wall", "obstacle", "level", "unit", "fight", "battle",
+    "match", "map", "enemy", "army",
+];
+
+#[derive(Clone, Debug)]
+pub enum GuardrailViolation {
+    TooLong {
+        chars: usize,
+    },
+
+    /// Prompt tries to override the instructions
+    Injection {
+        phrase: String,
+    },
+
+    Abusive,
+
+    OffTopic,
+
+    /// Response fields used for something other than what the schema is for
+    SchemaAbuse {
+        reason: String,
+    },
+}
+
+impl GuardrailViolation {
+    /// Shown to the player as the level error
+    pub fn reason(&self) -> String {
+        match self {
+            GuardrailViolation::TooLong { chars } => format!(
+                "Description is {} characters, the limit is {}",
+                chars, MAX_PROMPT_CHARS
+            ),
+            GuardrailViolation::Injection { phrase } => format!(
+                "Description looks like it is giving the model instructions (\"{}\")",
+                phrase
+            ),
+            GuardrailViolation::Abusive => "Description contains abusive content".to_string(),
+            GuardrailViolation::OffTopic => "Description isn't about an arena level".to_string(),
+            GuardrailViolation::SchemaAbuse { reason } => {
+                format!("Model response was not a level. {}", reason)
+            }
+        }
+    }
+}
+
+/// Check the prompt before it is sent to the model
+pub fn check_input(prompt: &str) -> Result<(), GuardrailViolation> {
+    let chars = prompt.chars().count();
+    if chars > MAX_PROMPT_CHARS {
+        return Err(GuardrailViolation::TooLong { chars });
+    }
+
+    // collapse whitespace and punctuation so spacing tricks don't slip through
+    let normalized = prompt
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let words: Vec<&str> = normalized.split(' ').collect();
+
+    if let Some(phrase) = INJECTION_PHRASES
+        .iter()
+        .find(|p| contains_phrase(&normalized, p))
+    {
+        return Err(GuardrailViolation::Injection {
+            phrase: phrase.to_string(),
+        });
+    }
+
+    if words.iter().any(|w| ABUSIVE_WORDS.contains(w)) {
+        return Err(GuardrailViolation::Abusive);
+    }
+
+    let about_arena = words
+        .iter()
+        .any(|w| ARENA_WORDS.iter().any(|a| w.starts_with(a)));
+    if !about_arena && words.iter().any(|w| ABUSIVE_OFF_ARENA_WORDS.contains(w)) {
+        return Err(GuardrailViolation::Abusive);
+    }
+
+    let off_topic = OFF_TOPIC_PHRASES
+        .iter()
+        .any(|p| contains_phrase(&normalized, p));
+    if off_topic && !about_arena {
+        return Err(GuardrailViolation::OffTopic);
+    }
+
+    Ok(())
+}
+
+/// Check the parsed response for fields stuffed with things that aren't level data
+pub fn check_output(resp: &LevelGenResponse) -> Result<(), GuardrailViolation> {
+    let abuse = |reason: String| Err(GuardrailViolation::SchemaAbuse { reason });
+
+    if resp.error.chars().count() > MAX_ERROR_CHARS {
+        return abuse(format!(
+            "Error message is over {} characters",
+            MAX_ERROR_CHARS
+        ));
+    }
+
+    let lower_error = resp.error.to_lowercase();
+    if ["http://", "https://", "```", "<script"]
+        .iter()
+        .any(|s| lower_error.contains(s))
+    {
+        return abuse("Error message contains links or code".to_string());
+    }
+
+    let mut fields: Vec<&str> = vec![];
+    for behavior in &resp.behaviors {
+        fields.push(&behavior.team);
+        for rule in &behavior.rules {
+            fields.extend([
+                rule.condition.as_str(),
+                rule.action.as_str(),
+                rule.target.as_str(),
+            ]);
+        }
+    }
+    if let Some(rules) = &resp.rules {
+        fields.extend([rules.objective.as_str(), rules.team.as_str()]);
+    }
+
+    if let Some(field) = fields.iter().find(|f| f.chars().count() > MAX_FIELD_CHARS) {
+        return abuse(format!(
+            "A field holds {} characters where a short keyword belongs",
+            field.chars().count()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Match whole words only, so "show to" doesn't match "how to"
+fn contains_phrase(normalized: &str, phrase: &str) -> bool {
+    format!(" {} ", normalized).contains(&format!(" {} ", phrase))
+}
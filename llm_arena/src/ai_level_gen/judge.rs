@@ -0,0 +1,91 @@
+use crate::ai_level_gen::*;
+use kalosm::language::*;
+use serde::{Deserialize, Serialize};
+use tracing::{Instrument, info, info_span};
+
+pub const JUDGE_MIN_SCORE: i32 = 1;
+pub const JUDGE_MAX_SCORE: i32 = 10;
+
+/// Judge's opinion of how well a placed level matches the prompt
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct JudgeResponse {
+    /// 1 to 10. 10 is a perfect match for the prompt.
+    pub score: i32,
+
+    /// Short explanation of the score
+    pub critique: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct JudgeOutput {
+    pub response: JudgeResponse,
+    pub raw_response: String,
+    pub model: String,
+    pub usage: Usage,
+}
+
+/// Ask a second model to score a placed level against the prompt it was generated from.
+/// level_description is the text from Level::describe
+pub async fn judge(prompt: &str, level_description: &str) -> Result<JudgeOutput, AIError> {
+    let message = format!(
+        "Description: {}\nPlaced level:\n{}",
+        prompt, level_description
+    );
+
+    let mut entry = AuditEntry::start("judge", Backend::OpenAI, MODEL_NAME, &message);
+    let result = run_judge(&message, &mut entry)
+        .instrument(info_span!("request", kind = "judge", model = MODEL_NAME))
+        .await;
+    entry.finish(result.as_ref().map(|output| &output.response));
+    result
+}
+
+async fn run_judge(message: &str, entry: &mut AuditEntry) -> Result<JudgeOutput, AIError> {
+    info!("Start judging");
+
+    let llm = OpenAICompatibleChatModel::builder()
+        .with_gpt_4o_mini()
+        .build();
+
+    let schema: String = JudgeResponse::schema().to_string();
+    let system_prompt = format!(
+        "You judge levels for an arena game where a team of squares fights a team of circles. \
+        You are given the description the level was made from and a summary of the level that was placed. \
+        Score from {} to {} how well the level matches the description. \
+        Consider the team sizes, where units are placed, walls, obstacles, behaviors and match rules. \
+        Keep the critique to a few sentences and name anything that is missing or wrong. \
+        Respond in formatted json following this schema {}. ",
+        JUDGE_MIN_SCORE, JUDGE_MAX_SCORE, schema
+    );
+    let task = llm.task(&system_prompt);
+
+    let full_prompt = format!("{}{}", system_prompt, message);
+    let reservation = check_spending_cap(MODEL_NAME, &full_prompt)?;
+    let task = &task;
+    let mut call = RateLimitedCall::new(Backend::OpenAI, estimate_tokens(&full_prompt));
+    let result = call
+        .run(move || async move { Ok(task(message).await?) })
+        .await;
+    entry.retries = call.retries();
+    let response_text = result?;
+
+    let usage = record_usage(reservation, "judge", &full_prompt, &response_text);
+    entry.usage = usage;
+
+    let trimmed = trim_response(&response_text);
+    entry.raw_response = trimmed.to_string();
+
+    let mut response: JudgeResponse = {
+        let _parse = info_span!("parse").entered();
+        serde_json::from_str(trimmed)?
+    };
+    response.score = response.score.clamp(JUDGE_MIN_SCORE, JUDGE_MAX_SCORE);
+    info!(score = response.score, "Judged level");
+
+    Ok(JudgeOutput {
+        response,
+        raw_response: trimmed.to_string(),
+        model: MODEL_NAME.to_string(),
+        usage,
+    })
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+/// Template used when no other is chosen
+pub const DEFAULT_CLASSIFY_TEMPLATE_ID: &str = "classify_v1";
+const DEFAULT_CLASSIFY_TEMPLATE: &str = include_str!("../../resources/prompts/classify_v1.txt");
+
+/// Placeholders a template can use. Each is written as {{name}} in the template.
+pub const TEMPLATE_PLACEHOLDERS: [&str; 4] = ["schema", "examples", "arena_radius", "entity_size"];
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(std::io::Error),
+
+    /// Template uses a placeholder that isn't in TEMPLATE_PLACEHOLDERS
+    UnknownPlaceholder {
+        name: String,
+    },
+
+    /// {{ without a closing }}
+    Unclosed,
+
+    /// Template file names are name_vN.txt
+    BadFileName {
+        file_name: String,
+    },
+}
+
+impl From<std::io::Error> for TemplateError {
+    fn from(err: std::io::Error) -> Self {
+        TemplateError::Io(err)
+    }
+}
+
+/// Values filled into a template
+pub struct TemplateVars {
+    pub schema: String,
+    pub examples: String,
+    pub arena_radius: f64,
+    pub entity_size: f64,
+}
+
+/// System prompt loaded from a versioned file.
+/// The id is the file name without the extension, like classify_v2, and is recorded on every result.
+#[derive(Clone, Debug)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub text: String,
+}
+
+impl PromptTemplate {
+    pub fn default_classify() -> Self {
+        Self {
+            id: DEFAULT_CLASSIFY_TEMPLATE_ID.to_string(),
+            text: DEFAULT_CLASSIFY_TEMPLATE.to_string(),
+        }
+    }
+
+    /// Load and check a template file named name_vN.txt
+    pub fn load(path: &Path) -> Result<Self, TemplateError> {
+        let id = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        let versioned = id
+            .rsplit_once("_v")
+            .map(|(name, version)| !name.is_empty() && version.parse::<u32>().is_ok())
+            .unwrap_or(false);
+        if !versioned {
+            return Err(TemplateError::BadFileName {
+                file_name: path.display().to_string(),
+            });
+        }
+
+        let template = Self {
+            id,
+            text: std::fs::read_to_string(path)?,
+        };
+
+        // catch bad placeholders on load instead of on the first classification
+        template.placeholders()?;
+        Ok(template)
+    }
+
+    pub fn render(&self, vars: &TemplateVars) -> Result<String, TemplateError> {
+        let mut out = String::new();
+        let mut rest = self.text.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or(TemplateError::Unclosed)?;
+            let name = after[..end].trim();
+
+            match name {
+                "schema" => out.push_str(&vars.schema),
+                "examples" => out.push_str(&vars.examples),
+                "arena_radius" => out.push_str(&vars.arena_radius.to_string()),
+                "entity_size" => out.push_str(&vars.entity_size.to_string()),
+                _ => {
+                    return Err(TemplateError::UnknownPlaceholder {
+                        name: name.to_string(),
+                    });
+                }
+            }
+
+            rest = &after[end + 2..];
+        }
+
+        out.push_str(rest);
+        Ok(out.trim_end_matches('\n').to_string())
+    }
+
+    /// Names of the placeholders used, in order
+    pub fn placeholders(&self) -> Result<Vec<String>, TemplateError> {
+        let mut names: Vec<String> = vec![];
+        let mut rest = self.text.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or(TemplateError::Unclosed)?;
+            let name = after[..end].trim();
+
+            if !TEMPLATE_PLACEHOLDERS.contains(&name) {
+                return Err(TemplateError::UnknownPlaceholder {
+                    name: name.to_string(),
+                });
+            }
+            names.push(name.to_string());
+
+            rest = &after[end + 2..];
+        }
+
+        Ok(names)
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{ai_level_gen::*, rng::*};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often a waiting request checks the limits again
+const WAIT_POLL: Duration = Duration::from_millis(50);
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+static RATE_LIMITERS: LazyLock<Mutex<HashMap<Backend, RateLimiter>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Clone, Debug)]
+pub struct RateLimits {
+    pub requests_per_minute: usize,
+
+    /// Estimated prompt tokens per minute
+    pub tokens_per_minute: u64,
+
+    /// Most requests in flight at once
+    pub max_concurrent: usize,
+
+    /// Retries after a 429 or 5xx before the error is returned
+    pub max_retries: u32,
+
+    /// Backoff doubles from base up to max, then gets jitter
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RateLimits {
+    pub fn for_backend(backend: Backend) -> Self {
+        match backend {
+            Backend::OpenAI => Self {
+                requests_per_minute: 500,
+                tokens_per_minute: 200_000,
+                max_concurrent: 4,
+                max_retries: 4,
+                base_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(30),
+            },
+            // runs locally, nothing to limit
+            Backend::Offline => Self {
+                requests_per_minute: usize::MAX,
+                tokens_per_minute: u64::MAX,
+                max_concurrent: usize::MAX,
+                max_retries: 0,
+                base_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            },
+        }
+    }
+}
+
+/// Snapshot of a backend's limiter for the UI
+#[derive(Clone, Debug)]
+pub struct RateLimitStatus {
+    pub backend: Backend,
+    pub requests_last_minute: usize,
+    pub tokens_last_minute: u64,
+    pub in_flight: usize,
+
+    /// Requests that had to wait for the rate or concurrency limit
+    pub throttled: u32,
+    pub retries: u32,
+
+    /// Seconds until requests are allowed again after a 429 or 5xx
+    pub backoff_left: f64,
+    pub last_error: Option<String>,
+
+    pub limits: RateLimits,
+}
+
+impl RateLimitStatus {
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "{} {}/{} req/min, {}/{} tok/min, {}/{} in flight, {} throttled, {} retries",
+            self.backend.name(),
+            self.requests_last_minute,
+            self.limits.requests_per_minute,
+            self.tokens_last_minute,
+            self.limits.tokens_per_minute,
+            self.in_flight,
+            self.limits.max_concurrent,
+            self.throttled,
+            self.retries
+        );
+        if self.backoff_left > 0.0 {
+            text.push_str(&format!(", backing off {:.1}s", self.backoff_left));
+        }
+        text
+    }
+}
+
+struct RateLimiter {
+    limits: RateLimits,
+
+    /// Start time and estimated tokens of requests in the last minute
+    window: VecDeque<(Instant, u64)>,
+    in_flight: usize,
+    backoff_until: Option<Instant>,
+
+    throttled: u32,
+    retries: u32,
+    last_error: Option<String>,
+
+    /// For backoff jitter
+    rng: Rng,
+}
+
+impl RateLimiter {
+    fn new(backend: Backend) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Self {
+            limits: RateLimits::for_backend(backend),
+            window: VecDeque::new(),
+            in_flight: 0,
+            backoff_until: None,
+            throttled: 0,
+            retries: 0,
+            last_error: None,
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some((start, _)) = self.window.front() {
+            if now.duration_since(*start) < RATE_WINDOW {
+                break;
+            }
+            self.window.pop_front();
+        }
+    }
+
+    /// Take a slot if every limit allows it
+    fn try_start(&mut self, tokens: u64, now: Instant) -> bool {
+        self.prune(now);
+
+        if self.backoff_until.map(|until| now < until).unwrap_or(false) {
+            return false;
+        }
+        if self.in_flight >= self.limits.max_concurrent {
+            return false;
+        }
+        if self.window.len() >= self.limits.requests_per_minute {
+            return false;
+        }
+
+        // a single request over the token limit still goes once the window is empty
+        let window_tokens: u64 = self.window.iter().map(|(_, t)| t).sum();
+        if !self.window.is_empty() && window_tokens + tokens > self.limits.tokens_per_minute {
+            return false;
+        }
+
+        self.window.push_back((now, tokens));
+        self.in_flight += 1;
+        true
+    }
+
+    fn backoff(&mut self, attempt: u32) -> Duration {
+        let exponential = self
+            .limits
+            .base_backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.limits.max_backoff);
+
+        // jitter between half and all of the backoff so retries don't line up
+        let delay = exponential.mul_f64(0.5 + self.rng.next_f64() * 0.5);
+        self.backoff_until = Some(Instant::now() + delay);
+        delay
+    }
+
+    fn status(&self, backend: Backend) -> RateLimitStatus {
+        let now = Instant::now();
+        let recent = self
+            .window
+            .iter()
+            .filter(|(start, _)| now.duration_since(*start) < RATE_WINDOW);
+
+        RateLimitStatus {
+            backend,
+            requests_last_minute: recent.clone().count(),
+            tokens_last_minute: recent.map(|(_, t)| t).sum(),
+            in_flight: self.in_flight,
+            throttled: self.throttled,
+            retries: self.retries,
+            backoff_left: self
+                .backoff_until
+                .map(|until| until.saturating_duration_since(now).as_secs_f64())
+                .unwrap_or(0.0),
+            last_error: self.last_error.clone(),
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+fn with_limiter<T>(backend: Backend, f: impl FnOnce(&mut RateLimiter) -> T) -> T {
+    let mut limiters = RATE_LIMITERS.lock().unwrap();
+    f(limiters
+        .entry(backend)
+        .or_insert_with(|| RateLimiter::new(backend)))
+}
+
+pub fn set_rate_limits(backend: Backend, limits: RateLimits) {
+    with_limiter(backend, |limiter| limiter.limits = limits);
+}
+
+/// Status of every backend that has been used
+pub fn rate_limit_status() -> Vec<RateLimitStatus> {
+    let limiters = RATE_LIMITERS.lock().unwrap();
+    let mut status: Vec<RateLimitStatus> = limiters
+        .iter()
+        .map(|(backend, limiter)| limiter.status(*backend))
+        .collect();
+    status.sort_by_key(|s| s.backend.name());
+    status
+}
+
+/// Concurrency slot taken by start. Dropping it gives the slot back, so a request
+/// that's dropped part way through an attempt doesn't hold the slot forever.
+struct Slot {
+    backend: Backend,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        with_limiter(self.backend, |limiter| {
+            limiter.in_flight = limiter.in_flight.saturating_sub(1)
+        });
+    }
+}
+
+/// One model request going through a backend's limiter, including its retries.
+/// Call start before each attempt and finish with the attempt's result.
+pub struct RateLimitedCall {
+    backend: Backend,
+    tokens: u64,
+    attempt: u32,
+    slot: Option<Slot>,
+}
+
+impl RateLimitedCall {
+    /// tokens is the estimated prompt size
+    pub fn new(backend: Backend, tokens: u64) -> Self {
+        Self {
+            backend,
+            tokens,
+            attempt: 0,
+            slot: None,
+        }
+    }
+
+    /// Wait until the rate, token and concurrency limits allow the request
+    pub async fn start(&mut self) {
+        let mut waited = false;
+        while !with_limiter(self.backend, |l| l.try_start(self.tokens, Instant::now())) {
+            if !waited {
+                waited = true;
+                with_limiter(self.backend, |l| l.throttled += 1);
+            }
+            tokio::time::sleep(WAIT_POLL).await;
+        }
+        self.slot = Some(Slot {
+            backend: self.backend,
+        });
+    }
+
+    /// Attempts that failed and were run again
+    pub fn retries(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Run the request through the limiter, retrying 429 and 5xx errors
+    pub async fn run<T, Fut>(&mut self, mut request: impl FnMut() -> Fut) -> Result<T, AIError>
+    where
+        Fut: Future<Output = Result<T, AIError>>,
+    {
+        loop {
+            self.start().await;
+            let result = request().await;
+            if let Some(result) = self.finish(result).await {
+                return result;
+            }
+        }
+    }
+
+    /// Release the slot. Returns None when the attempt hit a 429 or 5xx and should be
+    /// run again, after waiting out the backoff.
+    pub async fn finish<T>(&mut self, result: Result<T, AIError>) -> Option<Result<T, AIError>> {
+        // released before taking the lock below, the drop takes it too
+        self.slot = None;
+
+        let retry_delay = with_limiter(self.backend, |limiter| {
+            let Err(error) = &result else {
+                return None;
+            };
+            limiter.last_error = Some(format!("{:?}", error));
+
+            if !error.is_retryable() || self.attempt >= limiter.limits.max_retries {
+                return None;
+            }
+
+            limiter.retries += 1;
+            Some(limiter.backoff(self.attempt))
+        });
+
+        match retry_delay {
+            Some(delay) => {
+                tracing::warn!(
+                    backend = self.backend.name(),
+                    delay_secs = delay.as_secs_f64(),
+                    "Request failed, retrying"
+                );
+                self.attempt += 1;
+                tokio::time::sleep(delay).await;
+                None
+            }
+            None => Some(result),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{ai_level_gen::*, level::*};
+use elara_engine::vectors::*;
+use kalosm::language::*;
+use serde::{Deserialize, Serialize};
+use tracing::{Instrument, debug, info, info_span, warn};
+
+/// Most rounds of calls the model gets before the level is taken as is
+pub const MAX_TOOL_TURNS: usize = 12;
+
+/// Most tool calls across all turns
+pub const MAX_TOOL_CALLS: usize = 200;
+
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct ToolCallGen {
+    /// "place_square", "place_circle", "clear" or "describe_level"
+    pub tool: String,
+
+    /// Position for place_square and place_circle. Unused by other tools.
+    pub x: f64,
+    pub y: f64,
+}
+
+/// One round of calls from the model
+#[derive(Parse, Clone, Debug, Schema, Serialize, Deserialize)]
+pub struct ToolTurnGen {
+    /// Run in order. Results are sent back before the next turn.
+    pub calls: Vec<ToolCallGen>,
+
+    /// True once the level matches the description
+    pub done: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ToolLogEntry {
+    pub turn: usize,
+    pub call: ToolCallGen,
+    pub result: String,
+}
+
+impl ToolLogEntry {
+    pub fn label(&self) -> String {
+        match self.call.tool.as_str() {
+            "place_square" | "place_circle" => format!(
+                "{}. {}({:.0}, {:.0}) {}",
+                self.turn, self.call.tool, self.call.x, self.call.y, self.result
+            ),
+            _ => format!("{}. {}() {}", self.turn, self.call.tool, self.result),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ToolGenOutput {
+    pub level: Level,
+
+    /// Counts of the built level, so the rest of the pipeline can treat it like a classification
+    pub response: LevelGenResponse,
+
+    pub log: Vec<ToolLogEntry>,
+
+    /// Every model response in order
+    pub raw_responses: Vec<String>,
+    pub model: String,
+
+    /// Stopped by the turn or call limit instead of the model finishing
+    pub hit_limit: bool,
+
+    /// Summed over every turn
+    pub usage: Usage,
+}
+
+impl ToolGenOutput {
+    pub fn to_level_gen_output(&self) -> LevelGenOutput {
+        LevelGenOutput {
+            response: self.response.clone(),
+            raw_response: self.raw_responses.join("\n"),
+            model: self.model.clone(),
+            template_id: String::new(),
+            usage: self.usage,
+        }
+    }
+}
+
+/// Let the model build the level itself by calling placement tools in a loop.
+/// Each turn's calls are run against the level and the results are sent back to the model.
+pub async fn generate_with_tools(
+    prompt: &str,
+    limits: &ValidationLimits,
+) -> Result<ToolGenOutput, AIError> {
+    info!("Start tool generation");
+
+    if let Err(violation) = check_input(prompt) {
+        warn!(?violation, "Prompt blocked by guardrail");
+        let response = LevelGenResponse::invalid(violation.reason());
+        AuditEntry::start("tools", Backend::OpenAI, GUARDRAIL_MODEL, prompt).finish(Ok(&response));
+
+        return Ok(ToolGenOutput {
+            level: Level::new(),
+            response,
+            log: vec![],
+            raw_responses: vec![],
+            model: GUARDRAIL_MODEL.to_string(),
+            hit_limit: false,
+            usage: Usage::default(),
+        });
+    }
+
+    let llm = OpenAICompatibleChatModel::builder()
+        .with_gpt_4o_mini()
+        .build();
+
+    let schema: String = ToolTurnGen::schema().to_string();
+    let system_prompt = format!(
+        "You build levels for an arena game where a team of squares fights a team of circles. \
+        The arena is a circle of radius {} centered on 0,0. Entities are {} wide. \
+        Build the level the user describes by calling tools. \
+        place_square and place_circle put one entity at x,y. clear removes every entity. \
+        describe_level returns a summary of the level so far. \
+        You can make at most {} calls over {} turns. \
+        After each turn you get the result of every call. Set done once the level matches the description. \
+        Respond in formatted json following this schema {}. ",
+        GEN_RANGE, ENTITY_SIZE, MAX_TOOL_CALLS, MAX_TOOL_TURNS, schema
+    );
+    let mut chat = llm.chat().with_system_prompt(system_prompt.clone());
+
+    let mut level = Level::new();
+    let mut log: Vec<ToolLogEntry> = vec![];
+    let mut raw_responses: Vec<String> = vec![];
+    let mut calls_left = MAX_TOOL_CALLS;
+    let mut finished = false;
+    let mut usage = Usage::default();
+
+    // the whole conversation is sent again every turn
+    let mut history = system_prompt;
+
+    let mut message = format!("Description: {}", prompt);
+    for turn in 1..=MAX_TOOL_TURNS {
+        history.push_str(&message);
+        let reservation = check_spending_cap(MODEL_NAME, &history)?;
+
+        let mut entry = AuditEntry::start("tools", Backend::OpenAI, MODEL_NAME, &message);
+
+        let mut call = RateLimitedCall::new(Backend::OpenAI, estimate_tokens(&history));
+        let result = loop {
+            call.start().await;
+            let result = chat(&message)
+                .instrument(info_span!(
+                    "request",
+                    kind = "tools",
+                    model = MODEL_NAME,
+                    turn
+                ))
+                .await
+                .map_err(AIError::from);
+            if let Some(result) = call.finish(result).await {
+                break result;
+            }
+        };
+        entry.retries = call.retries();
+
+        let response_text = match result {
+            Ok(response_text) => response_text,
+            Err(error) => {
+                entry.finish::<ToolTurnGen>(Err(&error));
+                return Err(error);
+            }
+        };
+
+        entry.usage = record_usage(reservation, "tools", &history, &response_text);
+        usage.add(&entry.usage);
+        history.push_str(&response_text);
+
+        let trimmed = trim_response(&response_text);
+        raw_responses.push(trimmed.to_string());
+        entry.raw_response = trimmed.to_string();
+
+        let parsed = {
+            let _parse = info_span!("parse", turn).entered();
+            serde_json::from_str::<ToolTurnGen>(trimmed)
+        };
+        let turn_gen = match parsed {
+            Ok(turn_gen) => {
+                entry.finish(Ok(&turn_gen));
+                turn_gen
+            }
+            Err(error) => {
+                // let the model fix its own mistake, this still uses up a turn
+                message = format!("That response didn't follow the schema. {}", error);
+                entry.finish::<ToolTurnGen>(Err(&AIError::from(error)));
+                continue;
+            }
+        };
+
+        let mut results: Vec<String> = vec![];
+        for call in turn_gen.calls {
+            let result = if calls_left == 0 {
+                "skipped, out of tool calls".to_string()
+            } else {
+                calls_left -= 1;
+                run_tool(&call, &mut level, limits)
+            };
+
+            debug!(turn, ?call, result, "Tool call");
+            results.push(format!("{} -> {}", call.tool, result));
+            log.push(ToolLogEntry { turn, call, result });
+        }
+
+        if turn_gen.done || calls_left == 0 {
+            finished = turn_gen.done;
+            break;
+        }
+
+        message = format!(
+            "Results:\n{}\n{} calls and {} turns left.",
+            results.join("\n"),
+            calls_left,
+            MAX_TOOL_TURNS - turn
+        );
+    }
+
+    info!(
+        calls = MAX_TOOL_CALLS - calls_left,
+        "Finished tool generation"
+    );
+
+    let response = LevelGenResponse {
+        valid: true,
+        square_count: level.squares.len() as i32,
+        circle_count: level.circles.len() as i32,
+        ..Default::default()
+    };
+
+    Ok(ToolGenOutput {
+        level,
+        response,
+        log,
+        raw_responses,
+        model: MODEL_NAME.to_string(),
+        hit_limit: !finished,
+        usage,
+    })
+}
+
+/// Run one call against the level. Returns the result the model sees.
+fn run_tool(call: &ToolCallGen, level: &mut Level, limits: &ValidationLimits) -> String {
+    let (kind, max) = match call.tool.as_str() {
+        "place_square" => (EntityKind::Square, limits.max_squares),
+        "place_circle" => (EntityKind::Circle, limits.max_circles),
+        "clear" => {
+            level.squares.clear();
+            level.circles.clear();
+            return "cleared".to_string();
+        }
+        "describe_level" => return level.describe(),
+        _ => return format!("unknown tool {}", call.tool),
+    };
+
+    let pos = VecTwo::new(call.x, call.y);
+    if !pos.x.is_finite() || !pos.y.is_finite() {
+        return "position is not a number".to_string();
+    }
+    if distance(pos, VecTwo::new(0.0, 0.0)) > GEN_RANGE {
+        return "outside the arena".to_string();
+    }
+    if level.is_blocked(pos, ENTITY_SIZE * 0.5) {
+        return "blocked by level geometry".to_string();
+    }
+    if level.entities(kind).len() as i32 >= max {
+        return format!("already at the limit of {}", max);
+    }
+
+    let overlapping = [EntityKind::Square, EntityKind::Circle]
+        .iter()
+        .flat_map(|k| level.entities(*k))
+        .any(|e| distance(e.pos, pos) < ENTITY_SIZE);
+    if overlapping {
+        return "overlaps another entity".to_string();
+    }
+
+    level.entities_mut(kind).push(Entity::new(pos));
+    format!("placed, {} total", level.entities(kind).len())
+}
@@ -0,0 +1,382 @@
+use crate::{ai_level_gen::*, level::SaveError};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{LazyLock, Mutex},
+};
+
+/// Price table read at startup. The copy built into the game is used when it's missing.
+pub const PRICE_TABLE_PATH: &str = "resources/prices.json";
+const DEFAULT_PRICES: &str = include_str!("../../resources/prices.json");
+
+/// Rough characters per token for English text and json.
+/// The model client doesn't report usage so token counts are estimated from text length.
+const CHARS_PER_TOKEN: f64 = 4.0;
+
+/// Spending caps the UI cycles through, in dollars
+pub const SPENDING_CAP_OPTIONS: [Option<f64>; 4] = [None, Some(0.01), Some(0.1), Some(1.0)];
+
+/// Usage for every model call this session
+pub static SESSION_USAGE: LazyLock<Mutex<UsageTracker>> =
+    LazyLock::new(|| Mutex::new(UsageTracker::new(PriceTable::default())));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+
+    /// Dollars, from the price table at the time of the call
+    pub cost: f64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} prompt + {} completion tokens, ${:.4}",
+            self.prompt_tokens, self.completion_tokens, self.cost
+        )
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as f64 / CHARS_PER_TOKEN).ceil() as u64
+}
+
+/// Dollars per million tokens
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub model: String,
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct PriceTable {
+    pub prices: Vec<ModelPrice>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self {
+            prices: serde_json::from_str(DEFAULT_PRICES)
+                .expect("Default price table doesn't parse"),
+        }
+    }
+}
+
+impl PriceTable {
+    /// Load a json list of model prices
+    pub fn load(path: &Path) -> Result<Self, SaveError> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(Self {
+            prices: serde_json::from_str(&data)?,
+        })
+    }
+
+    pub fn is_priced(&self, model: &str) -> bool {
+        self.prices.iter().any(|p| p.model == model)
+    }
+
+    /// Models missing from the table cost nothing
+    pub fn cost(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        match self.prices.iter().find(|p| p.model == model) {
+            Some(price) => {
+                (prompt_tokens as f64 * price.prompt_per_million
+                    + completion_tokens as f64 * price.completion_per_million)
+                    / 1_000_000.0
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UsageRecord {
+    pub model: String,
+
+    /// What the call was for, like "classify" or "judge"
+    pub purpose: String,
+
+    pub usage: Usage,
+}
+
+#[derive(Clone, Debug)]
+pub struct UsageTracker {
+    pub prices: PriceTable,
+    pub records: Vec<UsageRecord>,
+
+    /// New calls fail once the session cost reaches this many dollars
+    pub spending_cap: Option<f64>,
+
+    /// Estimated prompt cost of the calls in flight, held against the cap until they're recorded
+    pub reserved: f64,
+}
+
+impl UsageTracker {
+    pub fn new(prices: PriceTable) -> Self {
+        Self {
+            prices,
+            records: vec![],
+            spending_cap: None,
+            reserved: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, model: &str, purpose: &str, prompt: &str, completion: &str) -> Usage {
+        let prompt_tokens = estimate_tokens(prompt);
+        let completion_tokens = estimate_tokens(completion);
+
+        if !self.prices.is_priced(model) && !self.records.iter().any(|r| r.model == model) {
+            tracing::warn!(
+                model,
+                "Model is missing from the price table, its calls count as free against the spending cap"
+            );
+        }
+
+        let usage = Usage {
+            prompt_tokens,
+            completion_tokens,
+            cost: self.prices.cost(model, prompt_tokens, completion_tokens),
+        };
+
+        tracing::info!(
+            model,
+            purpose,
+            prompt_tokens,
+            completion_tokens,
+            cost = usage.cost,
+            "Usage"
+        );
+        self.records.push(UsageRecord {
+            model: model.to_string(),
+            purpose: purpose.to_string(),
+            usage,
+        });
+
+        usage
+    }
+
+    pub fn total(&self) -> Usage {
+        let mut total = Usage::default();
+        for record in &self.records {
+            total.add(&record.usage);
+        }
+        total
+    }
+
+    /// Totals per model, in the order models were first used
+    pub fn by_model(&self) -> Vec<(String, Usage)> {
+        let mut totals: Vec<(String, Usage)> = vec![];
+        for record in &self.records {
+            match totals.iter_mut().find(|(model, _)| *model == record.model) {
+                Some((_, usage)) => usage.add(&record.usage),
+                None => totals.push((record.model.clone(), record.usage)),
+            }
+        }
+        totals
+    }
+
+    pub fn check_cap(&self) -> Result<(), AIError> {
+        let spent = self.total().cost;
+        match self.spending_cap {
+            Some(cap) if spent >= cap => Err(AIError::SpendingCap { spent, cap }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Hold the estimated prompt cost of a new call. Fails when the spent and held dollars
+    /// would pass the cap, so calls started together can't all slip under it.
+    pub fn reserve(&mut self, model: &str, prompt: &str) -> Result<f64, AIError> {
+        let cost = self.prices.cost(model, estimate_tokens(prompt), 0);
+        let spent = self.total().cost + self.reserved;
+        match self.spending_cap {
+            Some(cap) if spent >= cap || spent + cost > cap => {
+                Err(AIError::SpendingCap { spent, cap })
+            }
+            _ => {
+                self.reserved += cost;
+                Ok(cost)
+            }
+        }
+    }
+
+    fn release(&mut self, cost: f64) {
+        self.reserved = (self.reserved - cost).max(0.0);
+    }
+}
+
+/// Estimated cost held against the spending cap for a call in flight.
+/// Released when recorded with record_usage, or when dropped if the call never finished.
+#[derive(Debug)]
+pub struct SpendingReservation {
+    model: String,
+    cost: f64,
+}
+
+impl Drop for SpendingReservation {
+    fn drop(&mut self) {
+        if self.cost > 0.0 {
+            SESSION_USAGE.lock().unwrap().release(self.cost);
+        }
+    }
+}
+
+/// Fails when the session is over its spending cap. Call before every model request
+/// with the full prompt, then hand the reservation to record_usage.
+pub fn check_spending_cap(model: &str, prompt: &str) -> Result<SpendingReservation, AIError> {
+    let cost = SESSION_USAGE.lock().unwrap().reserve(model, prompt)?;
+    Ok(SpendingReservation {
+        model: model.to_string(),
+        cost,
+    })
+}
+
+/// Record a finished model request against the session, replacing its estimate with the actual usage
+pub fn record_usage(
+    mut reservation: SpendingReservation,
+    purpose: &str,
+    prompt: &str,
+    completion: &str,
+) -> Usage {
+    let mut usage = SESSION_USAGE.lock().unwrap();
+    usage.release(reservation.cost);
+
+    // already released, dropping it must not touch the lock again
+    reservation.cost = 0.0;
+    usage.record(&reservation.model, purpose, prompt, completion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+    use tracing_subscriber::{layer::Context, prelude::*};
+
+    /// One dollar per prompt token, so costs are easy to count
+    fn prices(model: &str) -> PriceTable {
+        PriceTable {
+            prices: vec![ModelPrice {
+                model: model.to_string(),
+                prompt_per_million: 1_000_000.0,
+                completion_per_million: 1_000_000.0,
+            }],
+        }
+    }
+
+    /// Three tokens
+    const PROMPT: &str = "twelve chars";
+
+    struct WarnCounter(Arc<AtomicUsize>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for WarnCounter {
+        fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+            if *event.metadata().level() == tracing::Level::WARN {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn count_warnings(f: impl FnOnce()) -> usize {
+        let warnings = Arc::new(AtomicUsize::new(0));
+        let subscriber = tracing_subscriber::registry().with(WarnCounter(warnings.clone()));
+        tracing::subscriber::with_default(subscriber, f);
+        warnings.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn reservations_cant_pass_the_cap_together() {
+        let mut tracker = UsageTracker::new(prices("m"));
+        tracker.spending_cap = Some(10.0);
+
+        // calls started together, none recorded yet
+        for _ in 0..3 {
+            assert_eq!(tracker.reserve("m", PROMPT).unwrap(), 3.0);
+        }
+        assert_eq!(tracker.reserved, 9.0);
+        assert!(matches!(
+            tracker.reserve("m", PROMPT),
+            Err(AIError::SpendingCap { .. })
+        ));
+
+        // a finished call frees its estimate for the next one
+        tracker.release(3.0);
+        assert!(tracker.reserve("m", PROMPT).is_ok());
+    }
+
+    #[test]
+    fn recorded_cost_counts_against_the_cap() {
+        let mut tracker = UsageTracker::new(prices("m"));
+        tracker.spending_cap = Some(10.0);
+
+        let cost = tracker.reserve("m", PROMPT).unwrap();
+        tracker.release(cost);
+        tracker.record("m", "classify", PROMPT, "twelve chars");
+        assert_eq!(tracker.total().cost, 6.0);
+
+        assert!(tracker.reserve("m", PROMPT).is_ok());
+        assert!(tracker.reserve("m", PROMPT).is_err());
+    }
+
+    #[test]
+    fn no_cap_reserves_anything() {
+        let mut tracker = UsageTracker::new(prices("m"));
+        for _ in 0..100 {
+            assert!(tracker.reserve("m", PROMPT).is_ok());
+        }
+    }
+
+    #[test]
+    fn dropped_reservation_is_released() {
+        let model = "reservation-test-model";
+        SESSION_USAGE
+            .lock()
+            .unwrap()
+            .prices
+            .prices
+            .extend(prices(model).prices);
+
+        let reserved = || SESSION_USAGE.lock().unwrap().reserved;
+        let before = reserved();
+
+        // the call failed or its future was dropped before it was recorded
+        let reservation = check_spending_cap(model, PROMPT).unwrap();
+        assert_eq!(reserved(), before + 3.0);
+        drop(reservation);
+        assert_eq!(reserved(), before);
+
+        // recording hands the estimate back and doesn't release it twice
+        let reservation = check_spending_cap(model, PROMPT).unwrap();
+        let usage = record_usage(reservation, "test", PROMPT, "");
+        assert_eq!(usage.cost, 3.0);
+        assert_eq!(reserved(), before);
+    }
+
+    #[test]
+    fn unpriced_model_is_free_and_warned_once() {
+        let mut tracker = UsageTracker::new(prices("m"));
+        tracker.spending_cap = Some(1.0);
+
+        let warnings = count_warnings(|| {
+            let usage = tracker.record("unknown", "classify", PROMPT, PROMPT);
+            assert_eq!(usage.cost, 0.0);
+            tracker.record("unknown", "classify", PROMPT, PROMPT);
+        });
+        assert_eq!(warnings, 1);
+        assert!(!tracker.prices.is_priced("unknown"));
+        assert!(tracker.reserve("unknown", PROMPT).is_ok());
+
+        let warnings = count_warnings(|| {
+            tracker.record("m", "classify", "", "");
+        });
+        assert_eq!(warnings, 0);
+    }
+}
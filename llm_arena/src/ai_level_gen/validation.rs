@@ -0,0 +1,422 @@
+use crate::{
+    ai_level_gen::*,
+    level::{ENTITY_SIZE, Entity, GEN_RANGE, Level, MAX_ENTITY_SIZE, MIN_ENTITY_SIZE},
+    sim::{check_rules, sanitize_behaviors},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    /// Fix out of range values and keep the level
+    Clamp,
+
+    /// Any issue throws out the whole level
+    Reject,
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationLimits {
+    pub max_squares: i32,
+    pub max_circles: i32,
+    pub max_walls: usize,
+    pub max_obstacles: usize,
+    pub max_obstacle_points: usize,
+
+    pub policy: ValidationPolicy,
+}
+
+impl Default for ValidationLimits {
+    fn default() -> Self {
+        Self {
+            max_squares: 200,
+            max_circles: 200,
+            max_walls: 50,
+            max_obstacles: 20,
+            max_obstacle_points: 16,
+
+            policy: ValidationPolicy::Clamp,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationReport {
+    pub issues: Vec<String>,
+    pub rejected: bool,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Check the model response against the limits. Clamps the response in place.
+/// With the Reject policy any issue marks the report as rejected and the response should not be placed.
+pub fn validate(resp: &mut LevelGenResponse, limits: &ValidationLimits) -> ValidationReport {
+    let mut issues: Vec<String> = vec![];
+
+    resp.square_count = clamp_count(
+        "square_count",
+        resp.square_count,
+        limits.max_squares,
+        &mut issues,
+    );
+    resp.circle_count = clamp_count(
+        "circle_count",
+        resp.circle_count,
+        limits.max_circles,
+        &mut issues,
+    );
+
+    truncate_list("walls", &mut resp.walls, limits.max_walls, &mut issues);
+
+    for (i, wall) in resp.walls.iter_mut().enumerate() {
+        let name = format!("wall {}", i);
+        clamp_point(&name, &mut wall.start_x, &mut wall.start_y, &mut issues);
+        clamp_point(&name, &mut wall.end_x, &mut wall.end_y, &mut issues);
+    }
+
+    truncate_list(
+        "obstacles",
+        &mut resp.obstacles,
+        limits.max_obstacles,
+        &mut issues,
+    );
+
+    for (i, obstacle) in resp.obstacles.iter_mut().enumerate() {
+        let name = format!("obstacle {}", i);
+
+        if obstacle.points.len() > limits.max_obstacle_points {
+            issues.push(format!(
+                "{} has {} points, over the limit of {}",
+                name,
+                obstacle.points.len(),
+                limits.max_obstacle_points
+            ));
+            obstacle.points.truncate(limits.max_obstacle_points);
+        }
+
+        for p in &mut obstacle.points {
+            clamp_point(&name, &mut p.x, &mut p.y, &mut issues);
+        }
+    }
+
+    let before = resp.obstacles.len();
+    resp.obstacles.retain(|o| o.points.len() >= 3);
+    if resp.obstacles.len() != before {
+        issues.push(format!(
+            "Removed {} obstacles with fewer than three points",
+            before - resp.obstacles.len()
+        ));
+    }
+
+    for error in sanitize_behaviors(&mut resp.behaviors) {
+        issues.push(format!("Behavior removed. {}", error));
+    }
+
+    if let Some(rules) = &mut resp.rules {
+        if let Err(error) = check_rules(rules, resp.square_count, resp.circle_count, &mut issues) {
+            issues.push(format!("Rules removed. {}", error));
+            resp.rules = None;
+        }
+    }
+
+    let rejected = limits.policy == ValidationPolicy::Reject && !issues.is_empty();
+
+    ValidationReport { issues, rejected }
+}
+
+/// Check a level that was built without a model response, a loaded file or tool calls.
+/// Clamps the level in place. Same rejection rules as validate.
+pub fn validate_level(level: &mut Level, limits: &ValidationLimits) -> ValidationReport {
+    let mut issues: Vec<String> = vec![];
+
+    for (name, entities, max) in [
+        ("squares", &mut level.squares, limits.max_squares),
+        ("circles", &mut level.circles, limits.max_circles),
+    ] {
+        truncate_list(name, entities, max.max(0) as usize, &mut issues);
+        for (i, entity) in entities.iter_mut().enumerate() {
+            clamp_entity(&format!("{} {}", name, i), entity, &mut issues);
+        }
+    }
+
+    truncate_list("walls", &mut level.walls, limits.max_walls, &mut issues);
+    for (i, wall) in level.walls.iter_mut().enumerate() {
+        let name = format!("wall {}", i);
+        clamp_point(&name, &mut wall.start.x, &mut wall.start.y, &mut issues);
+        clamp_point(&name, &mut wall.end.x, &mut wall.end.y, &mut issues);
+    }
+
+    truncate_list(
+        "obstacles",
+        &mut level.obstacles,
+        limits.max_obstacles,
+        &mut issues,
+    );
+    for (i, obstacle) in level.obstacles.iter_mut().enumerate() {
+        let name = format!("obstacle {}", i);
+
+        if obstacle.points.len() > limits.max_obstacle_points {
+            issues.push(format!(
+                "{} has {} points, over the limit of {}",
+                name,
+                obstacle.points.len(),
+                limits.max_obstacle_points
+            ));
+            obstacle.points.truncate(limits.max_obstacle_points);
+        }
+
+        for p in &mut obstacle.points {
+            clamp_point(&name, &mut p.x, &mut p.y, &mut issues);
+        }
+    }
+
+    let before = level.obstacles.len();
+    level.obstacles.retain(|o| o.points.len() >= 3);
+    if level.obstacles.len() != before {
+        issues.push(format!(
+            "Removed {} obstacles with fewer than three points",
+            before - level.obstacles.len()
+        ));
+    }
+
+    for error in sanitize_behaviors(&mut level.behaviors) {
+        issues.push(format!("Behavior removed. {}", error));
+    }
+
+    let (square_count, circle_count) = (level.squares.len() as i32, level.circles.len() as i32);
+    if let Some(rules) = &mut level.rules {
+        if let Err(error) = check_rules(rules, square_count, circle_count, &mut issues) {
+            issues.push(format!("Rules removed. {}", error));
+            level.rules = None;
+        }
+    }
+
+    let rejected = limits.policy == ValidationPolicy::Reject && !issues.is_empty();
+
+    ValidationReport { issues, rejected }
+}
+
+fn truncate_list<T>(name: &str, items: &mut Vec<T>, max: usize, issues: &mut Vec<String>) {
+    if items.len() > max {
+        issues.push(format!(
+            "{} {} is over the limit of {}",
+            items.len(),
+            name,
+            max
+        ));
+        items.truncate(max);
+    }
+}
+
+fn clamp_entity(name: &str, entity: &mut Entity, issues: &mut Vec<String>) {
+    clamp_point(name, &mut entity.pos.x, &mut entity.pos.y, issues);
+
+    if !entity.rotation.is_finite() {
+        issues.push(format!("{} has a non finite rotation", name));
+        entity.rotation = 0.0;
+    }
+
+    if !entity.size.is_finite() {
+        issues.push(format!("{} has a non finite size", name));
+        entity.size = ENTITY_SIZE;
+    } else if !(MIN_ENTITY_SIZE..=MAX_ENTITY_SIZE).contains(&entity.size) {
+        issues.push(format!("{} has a size of {}", name, entity.size));
+        entity.size = entity.size.clamp(MIN_ENTITY_SIZE, MAX_ENTITY_SIZE);
+    }
+}
+
+fn clamp_count(name: &str, count: i32, max: i32, issues: &mut Vec<String>) -> i32 {
+    if count < 0 {
+        issues.push(format!("{} of {} is negative", name, count));
+        return 0;
+    }
+
+    if count > max {
+        issues.push(format!(
+            "{} of {} is over the limit of {}",
+            name, count, max
+        ));
+        return max;
+    }
+
+    count
+}
+
+/// Pull non finite or out of arena coordinates back inside the arena
+fn clamp_point(name: &str, x: &mut f64, y: &mut f64, issues: &mut Vec<String>) {
+    if !x.is_finite() || !y.is_finite() {
+        issues.push(format!("{} has a non finite point", name));
+        *x = 0.0;
+        *y = 0.0;
+        return;
+    }
+
+    let len = f64::sqrt(*x * *x + *y * *y);
+    if len > GEN_RANGE {
+        issues.push(format!("{} has a point outside the arena", name));
+        *x = *x / len * GEN_RANGE;
+        *y = *y / len * GEN_RANGE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elara_engine::vectors::VecTwo;
+
+    fn response(squares: i32, circles: i32) -> LevelGenResponse {
+        LevelGenResponse {
+            valid: true,
+            square_count: squares,
+            circle_count: circles,
+            ..Default::default()
+        }
+    }
+
+    fn wall(start_x: f64, end_x: f64) -> WallGen {
+        WallGen {
+            start_x,
+            start_y: 0.0,
+            end_x,
+            end_y: 0.0,
+        }
+    }
+
+    fn reject() -> ValidationLimits {
+        ValidationLimits {
+            policy: ValidationPolicy::Reject,
+            ..ValidationLimits::default()
+        }
+    }
+
+    #[test]
+    fn clean_response() {
+        let mut resp = response(5, 5);
+        resp.walls.push(wall(-50.0, 50.0));
+
+        let report = validate(&mut resp, &reject());
+        assert!(report.is_clean());
+        assert!(!report.rejected);
+    }
+
+    #[test]
+    fn counts_are_clamped() {
+        let limits = ValidationLimits::default();
+        let mut resp = response(limits.max_squares + 1, -4);
+
+        let report = validate(&mut resp, &limits);
+        assert_eq!(resp.square_count, limits.max_squares);
+        assert_eq!(resp.circle_count, 0);
+        assert_eq!(report.issues.len(), 2);
+        assert!(!report.rejected);
+    }
+
+    #[test]
+    fn reject_policy_rejects_any_issue() {
+        let mut resp = response(-1, 5);
+        assert!(validate(&mut resp, &reject()).rejected);
+    }
+
+    #[test]
+    fn walls_are_truncated_and_pulled_inside() {
+        let limits = ValidationLimits::default();
+        let mut resp = response(1, 1);
+        resp.walls = (0..limits.max_walls + 3).map(|_| wall(0.0, 10.0)).collect();
+        resp.walls[0] = wall(GEN_RANGE * 2.0, f64::NAN);
+
+        let report = validate(&mut resp, &limits);
+        assert_eq!(resp.walls.len(), limits.max_walls);
+        assert!((resp.walls[0].start_x - GEN_RANGE).abs() < 1e-9);
+        assert_eq!(resp.walls[0].end_x, 0.0);
+        assert_eq!(report.issues.len(), 3);
+    }
+
+    #[test]
+    fn small_obstacles_are_removed() {
+        let mut resp = response(1, 1);
+        resp.obstacles.push(ObstacleGen {
+            points: vec![PointGen { x: 0.0, y: 0.0 }, PointGen { x: 1.0, y: 0.0 }],
+        });
+
+        let report = validate(&mut resp, &ValidationLimits::default());
+        assert!(resp.obstacles.is_empty());
+        assert_eq!(report.issues.len(), 1);
+    }
+
+    #[test]
+    fn level_entities_are_truncated_and_clamped() {
+        let limits = ValidationLimits {
+            max_squares: 2,
+            ..ValidationLimits::default()
+        };
+        let mut level = Level::new();
+        for _ in 0..5 {
+            level.squares.push(Entity::new(VecTwo::new(0.0, 0.0)));
+        }
+        level
+            .circles
+            .push(Entity::new(VecTwo::new(0.0, GEN_RANGE * 3.0)));
+        level.circles.push(Entity {
+            pos: VecTwo::new(0.0, 0.0),
+            rotation: f64::INFINITY,
+            size: -1.0,
+        });
+
+        let report = validate_level(&mut level, &limits);
+        assert_eq!(level.squares.len(), 2);
+        assert!((level.circles[0].pos.y - GEN_RANGE).abs() < 1e-9);
+        assert_eq!(level.circles[1].rotation, 0.0);
+        assert_eq!(level.circles[1].size, MIN_ENTITY_SIZE);
+        assert_eq!(report.issues.len(), 4);
+    }
+
+    #[test]
+    fn editor_sizes_load_unchanged() {
+        let mut level = Level::new();
+        for size in [MIN_ENTITY_SIZE, ENTITY_SIZE, 140.0, MAX_ENTITY_SIZE] {
+            level.squares.push(Entity {
+                pos: VecTwo::new(0.0, 0.0),
+                rotation: 0.0,
+                size,
+            });
+        }
+
+        let limits = ValidationLimits {
+            policy: ValidationPolicy::Reject,
+            ..ValidationLimits::default()
+        };
+        let report = validate_level(&mut level, &limits);
+        assert!(report.is_clean(), "{:?}", report.issues);
+        assert!(!report.rejected);
+        assert_eq!(level.squares[3].size, MAX_ENTITY_SIZE);
+    }
+
+    #[test]
+    fn sizes_outside_the_editor_range_are_clamped() {
+        let mut level = Level::new();
+        for size in [MAX_ENTITY_SIZE * 2.0, f64::NAN] {
+            level.squares.push(Entity {
+                pos: VecTwo::new(0.0, 0.0),
+                rotation: 0.0,
+                size,
+            });
+        }
+
+        let report = validate_level(&mut level, &ValidationLimits::default());
+        assert_eq!(level.squares[0].size, MAX_ENTITY_SIZE);
+        assert_eq!(level.squares[1].size, ENTITY_SIZE);
+        assert_eq!(report.issues.len(), 2);
+    }
+
+    #[test]
+    fn clean_level() {
+        let mut level = Level::new();
+        level.squares.push(Entity::new(VecTwo::new(10.0, 10.0)));
+
+        let report = validate_level(&mut level, &reject());
+        assert!(report.is_clean());
+        assert!(!report.rejected);
+    }
+}
@@ -0,0 +1,116 @@
+//! Prompt evaluation. Classifies every prompt in a file and checks the results with the
+//! prompt fidelity checker. Pass a second template to compare the two on the same prompts.
+//!
+//! The offline fallback is off so an unreachable model shows up as errors.
+//!
+//! eval_runner [--template a.txt] [--compare b.txt] [--examples none|all|K|randomK] [--backend openai|offline] [--seed S] prompts.txt
+
+use llm_arena::{ai_level_gen::*, diagnostics::init_tracing};
+use std::path::Path;
+
+#[tokio::main]
+async fn main() {
+    init_tracing();
+
+    let mut config = ClassifyConfig {
+        offline_fallback: false,
+        ..ClassifyConfig::default()
+    };
+    let mut compare: Option<PromptTemplate> = None;
+    let mut seed: u64 = 0;
+    let mut prompts_path: Option<String> = None;
+
+    let load = |path: Option<String>| -> PromptTemplate {
+        let path = path.expect("template flag needs a path");
+        match PromptTemplate::load(Path::new(&path)) {
+            Ok(template) => template,
+            Err(error) => {
+                eprintln!("{}: error loading template {:?}", path, error);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--template" => config.template = load(args.next()),
+            "--examples" => {
+                config.example_selection = match args.next().as_deref() {
+                    Some("none") => ExampleSelection::None,
+                    Some("all") => ExampleSelection::All,
+                    Some(k) if k.starts_with("random") => {
+                        ExampleSelection::Random(k[6..].parse().unwrap_or(3))
+                    }
+                    Some(k) => ExampleSelection::TopK(
+                        k.parse().expect("--examples needs none, all, randomK or K"),
+                    ),
+                    None => panic!("--examples needs none, all, randomK or K"),
+                };
+            }
+            "--compare" => compare = Some(load(args.next())),
+            "--backend" => {
+                config.backend = match args.next().as_deref() {
+                    Some("openai") => Backend::OpenAI,
+                    Some("offline") => Backend::Offline,
+                    _ => panic!("--backend needs openai or offline"),
+                };
+            }
+            "--seed" => {
+                seed = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .expect("--seed needs a number");
+            }
+            _ => prompts_path = Some(arg),
+        }
+    }
+
+    let Some(prompts_path) = prompts_path else {
+        eprintln!(
+            "usage: eval_runner [--template a.txt] [--compare b.txt] [--examples none|all|K|randomK] [--backend openai|offline] [--seed S] prompts.txt"
+        );
+        std::process::exit(1);
+    };
+
+    let prompts = match load_eval_prompts(Path::new(&prompts_path)) {
+        Ok(prompts) => prompts,
+        Err(error) => {
+            eprintln!("{}: error loading prompts {:?}", prompts_path, error);
+            std::process::exit(1);
+        }
+    };
+
+    let mut reports = vec![evaluate_template(&prompts, &config, seed).await];
+    if let Some(compare) = compare {
+        // same examples so only the template differs
+        let compare_config = ClassifyConfig {
+            template: compare,
+            ..config.clone()
+        };
+        reports.push(evaluate_template(&prompts, &compare_config, seed).await);
+    }
+
+    // per prompt results side by side
+    for (i, prompt) in prompts.iter().enumerate() {
+        let results: Vec<String> = reports
+            .iter()
+            .map(|report| match &report.cases[i].fidelity {
+                Some(fidelity) => format!(
+                    "{} {}/{}",
+                    report.template_id,
+                    fidelity.pass_count(),
+                    fidelity.checks.len()
+                ),
+                None => format!("{} error", report.template_id),
+            })
+            .collect();
+        println!("{} | {}", prompt, results.join(" | "));
+    }
+
+    for report in &reports {
+        println!("{}", report.summary());
+    }
+
+    println!("usage {}", SESSION_USAGE.lock().unwrap().total().describe());
+}
@@ -0,0 +1,177 @@
+//! Filters and summarizes the generation audit log.
+//!
+//! log_report [--kind K] [--model M] [--backend B] [--template T] [--errors]
+//!            [--since UNIX_MS] [--contains TEXT] [--last N] [--list] [generation_log.jsonl]
+
+use llm_arena::ai_level_gen::*;
+use std::path::Path;
+
+#[derive(Default)]
+struct Filter {
+    kind: Option<String>,
+    model: Option<String>,
+    backend: Option<String>,
+    template: Option<String>,
+    errors_only: bool,
+    since_ms: Option<u64>,
+    contains: Option<String>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let field = |want: &Option<String>, have: &str| want.as_ref().is_none_or(|w| w == have);
+
+        field(&self.kind, &entry.kind)
+            && field(&self.model, &entry.model)
+            && field(&self.backend, &entry.backend)
+            && field(&self.template, &entry.template_id)
+            && (!self.errors_only || entry.error.is_some())
+            && self
+                .since_ms
+                .is_none_or(|since| entry.timestamp_ms >= since)
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|text| entry.prompt.to_lowercase().contains(&text.to_lowercase()))
+    }
+}
+
+fn main() {
+    let mut filter = Filter::default();
+    let mut last: Option<usize> = None;
+    let mut list = false;
+    let mut path = AUDIT_LOG_PATH.to_string();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| panic!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "--kind" => filter.kind = Some(value("--kind")),
+            "--model" => filter.model = Some(value("--model")),
+            "--backend" => filter.backend = Some(value("--backend")),
+            "--template" => filter.template = Some(value("--template")),
+            "--contains" => filter.contains = Some(value("--contains")),
+            "--since" => {
+                filter.since_ms = Some(value("--since").parse().expect("--since needs a number"))
+            }
+            "--last" => last = Some(value("--last").parse().expect("--last needs a number")),
+            "--errors" => filter.errors_only = true,
+            "--list" => list = true,
+            _ => path = arg,
+        }
+    }
+
+    let (entries, skipped) = match load_audit_log(Path::new(&path)) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("{}: error reading log {:?}", path, error);
+            std::process::exit(1);
+        }
+    };
+
+    let mut matched: Vec<&AuditEntry> = entries.iter().filter(|e| filter.matches(e)).collect();
+    if let Some(last) = last {
+        matched = matched.split_off(matched.len().saturating_sub(last));
+    }
+
+    if list {
+        for entry in &matched {
+            let outcome = match &entry.error {
+                Some(error) => format!("error {}", error),
+                None => entry
+                    .parsed
+                    .as_ref()
+                    .map(|p| p.to_string())
+                    .unwrap_or_default(),
+            };
+            println!(
+                "{} {} {} {} {}ms {} retries | {} | {}",
+                entry.timestamp_ms,
+                entry.kind,
+                entry.backend,
+                entry.model,
+                entry.latency_ms,
+                entry.retries,
+                entry.prompt.replace('\n', " "),
+                outcome
+            );
+        }
+    }
+
+    if skipped > 0 {
+        println!("{} lines didn't parse and were skipped", skipped);
+    }
+    print_summary(&matched);
+}
+
+fn print_summary(entries: &[&AuditEntry]) {
+    println!("{} entries", entries.len());
+    if entries.is_empty() {
+        return;
+    }
+
+    let errors = entries.iter().filter(|e| e.error.is_some()).count();
+    let retries: u32 = entries.iter().map(|e| e.retries).sum();
+    println!(
+        "{} errors ({:.1}%), {} retries",
+        errors,
+        errors as f64 / entries.len() as f64 * 100.0,
+        retries
+    );
+
+    let mut latencies: Vec<u64> = entries.iter().map(|e| e.latency_ms).collect();
+    latencies.sort();
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p).round() as usize];
+    println!(
+        "latency mean {:.0}ms, p50 {}ms, p95 {}ms, max {}ms",
+        latencies.iter().sum::<u64>() as f64 / latencies.len() as f64,
+        percentile(0.5),
+        percentile(0.95),
+        latencies[latencies.len() - 1]
+    );
+
+    let mut usage = Usage::default();
+    for entry in entries {
+        usage.add(&entry.usage);
+    }
+    println!("usage {}", usage.describe());
+
+    for (label, key) in [
+        (
+            "kind",
+            (|e: &AuditEntry| e.kind.clone()) as fn(&AuditEntry) -> String,
+        ),
+        ("model", |e: &AuditEntry| e.model.clone()),
+        ("template", |e: &AuditEntry| e.template_id.clone()),
+    ] {
+        // (value, count, errors) in order of first appearance
+        let mut groups: Vec<(String, usize, usize)> = vec![];
+        for entry in entries {
+            let value = key(entry);
+            let index = match groups.iter().position(|g| g.0 == value) {
+                Some(index) => index,
+                None => {
+                    groups.push((value, 0, 0));
+                    groups.len() - 1
+                }
+            };
+            groups[index].1 += 1;
+            if entry.error.is_some() {
+                groups[index].2 += 1;
+            }
+        }
+
+        println!("by {}", label);
+        for (value, count, errors) in groups {
+            let value = if value.is_empty() {
+                "(none)".to_string()
+            } else {
+                value
+            };
+            println!("  {} {} entries, {} errors", value, count, errors);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Headless balance testing. Runs many matches on saved level files and prints win rates,
+//! along with which facts from the prompt the saved level gets wrong.
+//!
+//! sim_runner [--matches N] [--seed S] [--replace] level.json ...
+//!
+//! --replace places the entities again from each match seed using the saved model response,
+//! instead of reusing the saved placement.
+
+use llm_arena::{ai_level_gen::*, level::*, sim::*};
+use std::path::Path;
+
+fn main() {
+    let mut batch = BatchConfig {
+        matches: 1000,
+        base_seed: 0,
+        sim: SimConfig::default(),
+    };
+    let mut replace = false;
+    let mut paths: Vec<String> = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--matches" => {
+                batch.matches = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| usage("--matches needs a number"));
+            }
+            "--seed" => {
+                batch.base_seed = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| usage("--seed needs a number"));
+            }
+            "--replace" => replace = true,
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        usage("no level files given");
+    }
+
+    for path in &paths {
+        let (source, level, load_report) =
+            match load_level(Path::new(path), &ValidationLimits::default()) {
+                Ok(loaded) => loaded,
+                Err(error) => {
+                    eprintln!("{}: error loading level {:?}", path, error);
+                    continue;
+                }
+            };
+        for issue in &load_report.issues {
+            eprintln!("{}: fixed on load, {}", path, issue);
+        }
+
+        let (report, _) = if replace {
+            let mut response: LevelGenResponse = match serde_json::from_str(&source.raw_response) {
+                Ok(response) => response,
+                Err(error) => {
+                    eprintln!("{}: saved response doesn't parse {:?}", path, error);
+                    continue;
+                }
+            };
+            validate(&mut response, &ValidationLimits::default());
+            run_layout_batch(&response, &batch)
+        } else {
+            run_batch(&level, &batch)
+        };
+
+        println!("{}: {}", path, report.summary());
+
+        // prompt fidelity of the saved response and placement
+        if let Ok(response) = serde_json::from_str::<LevelGenResponse>(&source.raw_response) {
+            let fidelity = check_fidelity(&source.prompt, &response, Some(&level));
+            if !fidelity.checks.is_empty() {
+                println!("{}: {}", path, fidelity.summary());
+                for check in fidelity.checks.iter().filter(|c| !c.passed()) {
+                    println!(
+                        "{}:   failed {} (got {})",
+                        path,
+                        check.expectation.describe(),
+                        check.detail
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("{}", error);
+    eprintln!("usage: sim_runner [--matches N] [--seed S] [--replace] level.json ...");
+    std::process::exit(1);
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    fs::File,
+    sync::{LazyLock, Mutex, Once},
+    time::{Duration, Instant},
+};
+use tracing::{Event, Subscriber, field::Field, span};
+use tracing_subscriber::{
+    EnvFilter, fmt,
+    layer::{Context, Layer},
+    prelude::*,
+    registry::LookupSpan,
+};
+
+/// Trace output, written fresh each run
+pub const TRACE_LOG_PATH: &str = "trace.log";
+
+/// Used when RUST_LOG isn't set. Our own debug output, only info from kalosm, tokio and the rest.
+pub const DEFAULT_TRACE_FILTER: &str = "llm_arena=debug,info";
+
+/// How many timings and events the overlay keeps
+pub const DEBUG_OVERLAY_LINES: usize = 10;
+
+/// Span around drawing the level. Kept apart from the generation timings since it closes every frame.
+pub const RENDER_SPAN: &str = "render";
+
+/// Recent span timings and events for the debug overlay
+pub static DEBUG_LOG: LazyLock<Mutex<DebugLog>> = LazyLock::new(|| Mutex::new(DebugLog::default()));
+
+static INIT_TRACING: Once = Once::new();
+
+#[derive(Clone, Debug)]
+pub struct SpanTiming {
+    pub name: &'static str,
+    pub fields: String,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct DebugLog {
+    pub timings: VecDeque<SpanTiming>,
+    pub events: VecDeque<String>,
+    pub last_render: Option<Duration>,
+}
+
+impl DebugLog {
+    fn record_span(&mut self, timing: SpanTiming) {
+        if timing.name == RENDER_SPAN {
+            self.last_render = Some(timing.duration);
+            return;
+        }
+
+        self.timings.push_back(timing);
+        if self.timings.len() > DEBUG_OVERLAY_LINES {
+            self.timings.pop_front();
+        }
+    }
+
+    fn record_event(&mut self, event: String) {
+        self.events.push_back(event);
+        if self.events.len() > DEBUG_OVERLAY_LINES {
+            self.events.pop_front();
+        }
+    }
+
+    /// Text lines for the overlay, newest first
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = vec![];
+
+        if let Some(render) = self.last_render {
+            lines.push(format!("render {:.2}ms", render.as_secs_f64() * 1000.0));
+        }
+
+        for timing in self.timings.iter().rev() {
+            lines.push(format!(
+                "{} {:.0}ms {}",
+                timing.name,
+                timing.duration.as_secs_f64() * 1000.0,
+                timing.fields
+            ));
+        }
+
+        for event in self.events.iter().rev() {
+            lines.push(event.clone());
+        }
+
+        lines
+    }
+}
+
+/// Send tracing output to the trace file and the debug overlay. Safe to call more than once.
+pub fn init_tracing() {
+    INIT_TRACING.call_once(|| {
+        let registry =
+            tracing_subscriber::registry().with(OverlayLayer.with_filter(trace_filter()));
+
+        let result = match File::create(TRACE_LOG_PATH) {
+            Ok(file) => registry
+                .with(
+                    fmt::layer()
+                        .with_ansi(false)
+                        .with_writer(Mutex::new(file))
+                        .with_filter(trace_filter()),
+                )
+                .try_init(),
+            Err(_) => registry.try_init(),
+        };
+
+        if let Err(error) = result {
+            eprintln!("Couldn't start tracing {:?}", error);
+        }
+    });
+}
+
+/// RUST_LOG when it's set, otherwise the default filter. Each layer needs its own.
+fn trace_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_TRACE_FILTER))
+}
+
+/// Collects span timings and events for the overlay
+struct OverlayLayer;
+
+/// When a span was first entered
+struct SpanStart(Instant);
+
+struct SpanFields(String);
+
+impl<S> Layer<S> for OverlayLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut fields = FieldText::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanFields(fields.0));
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        // async spans are entered on every poll, time from the first
+        let mut extensions = span.extensions_mut();
+        if extensions.get_mut::<SpanStart>().is_none() {
+            extensions.insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+
+        let extensions = span.extensions();
+        let Some(start) = extensions.get::<SpanStart>() else {
+            return;
+        };
+
+        DEBUG_LOG.lock().unwrap().record_span(SpanTiming {
+            name: span.name(),
+            fields: extensions
+                .get::<SpanFields>()
+                .map(|f| f.0.clone())
+                .unwrap_or_default(),
+            duration: start.0.elapsed(),
+        });
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut text = FieldText::default();
+        event.record(&mut text);
+
+        DEBUG_LOG
+            .lock()
+            .unwrap()
+            .record_event(format!("{} {}", event.metadata().level(), text.0));
+    }
+}
+
+/// Fields written as "message key=value"
+#[derive(Default)]
+struct FieldText(String);
+
+impl tracing::field::Visit for FieldText {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.push(field, &format!("{:?}", value));
+    }
+}
+
+impl FieldText {
+    fn push(&mut self, field: &Field, value: &str) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+
+        if field.name() == "message" {
+            self.0.push_str(value);
+        } else {
+            let _ = write!(self.0, "{}={}", field.name(), value);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use crate::{level::*, physics::*};
+use elara_engine::vectors::*;
+use std::f64::consts::PI;
+
+/// How close the mouse needs to be to grab a handle
+const HANDLE_RADIUS: f64 = 8.0;
+
+/// Distance of the rotate handle past the edge of the entity
+const ROTATE_HANDLE_OFFSET: f64 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorTool {
+    Select,
+    AddSquare,
+    AddCircle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityRef {
+    pub kind: EntityKind,
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Drag {
+    /// Offset from the entity center to where it was grabbed
+    Move {
+        offset: VecTwo,
+    },
+    Rotate,
+    Resize,
+}
+
+/// Mouse and keyboard state the editor needs for one frame.
+/// Built by the game loop so the editor doesn't depend on platform input.
+#[derive(Clone, Copy, Debug)]
+pub struct EditorInput {
+    /// Mouse position in world space
+    pub mouse: VecTwo,
+
+    pub press: bool,
+    pub held: bool,
+    pub delete: bool,
+}
+
+#[derive(Debug)]
+pub struct Editor {
+    pub enabled: bool,
+    pub tool: EditorTool,
+    pub selected: Option<EntityRef>,
+
+    pub snap: bool,
+    pub grid_size: f64,
+
+    /// Degrees
+    pub angle_snap: f64,
+
+    drag: Option<Drag>,
+
+    /// Dragged entity as it was when the drag started, to tell a click from an edit
+    drag_start: Option<Entity>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            tool: EditorTool::Select,
+            selected: None,
+
+            snap: false,
+            grid_size: 10.0,
+            angle_snap: 15.0,
+
+            drag: None,
+            drag_start: None,
+        }
+    }
+
+    /// Apply one frame of input to the level.
+    /// Returns true when an edit is finished and should be recorded in history.
+    pub fn update(&mut self, level: &mut Level, input: &EditorInput) -> bool {
+        if !self.enabled {
+            self.drag = None;
+            return false;
+        }
+
+        // selection may point at an entity that no longer exists after an undo
+        if let Some(sel) = self.selected {
+            if sel.index >= level.entities(sel.kind).len() {
+                self.selected = None;
+                self.drag = None;
+            }
+        }
+
+        if input.delete {
+            if let Some(sel) = self.selected.take() {
+                level.entities_mut(sel.kind).remove(sel.index);
+                self.drag = None;
+                return true;
+            }
+        }
+
+        if input.press {
+            match self.tool {
+                EditorTool::AddSquare => {
+                    return self.add(level, EntityKind::Square, input.mouse);
+                }
+                EditorTool::AddCircle => {
+                    return self.add(level, EntityKind::Circle, input.mouse);
+                }
+                EditorTool::Select => self.begin_drag(level, input.mouse),
+            }
+        }
+
+        if let (Some(drag), Some(sel)) = (self.drag, self.selected) {
+            if !input.held {
+                self.drag = None;
+                let entity = &level.entities(sel.kind)[sel.index];
+                return self
+                    .drag_start
+                    .take()
+                    .map(|start| !same_placement(&start, entity))
+                    .unwrap_or(false);
+            }
+
+            let snap = self.snap;
+            let grid = self.grid_size;
+            let angle_snap = self.angle_snap.to_radians();
+            let entity = &mut level.entities_mut(sel.kind)[sel.index];
+
+            match drag {
+                Drag::Move { offset } => {
+                    let mut pos = VecTwo::new(input.mouse.x - offset.x, input.mouse.y - offset.y);
+                    if snap {
+                        pos = VecTwo::new(snap_to(pos.x, grid), snap_to(pos.y, grid));
+                    }
+                    entity.pos = pos;
+                }
+                Drag::Rotate => {
+                    // handle sits above the entity, so straight up is zero rotation
+                    let mut rotation =
+                        f64::atan2(input.mouse.y - entity.pos.y, input.mouse.x - entity.pos.x)
+                            - (PI * 0.5);
+                    if snap {
+                        rotation = snap_to(rotation, angle_snap);
+                    }
+                    entity.rotation = rotation;
+                }
+                Drag::Resize => {
+                    let half = f64::max(
+                        (input.mouse.x - entity.pos.x).abs(),
+                        (input.mouse.y - entity.pos.y).abs(),
+                    );
+                    let mut size = half * 2.0;
+                    if snap {
+                        size = snap_to(size, grid);
+                    }
+                    entity.size = size.clamp(MIN_ENTITY_SIZE, MAX_ENTITY_SIZE);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// World position of the rotate handle for an entity
+    pub fn rotate_handle(entity: &Entity) -> VecTwo {
+        let dist = entity.size * 0.5 + ROTATE_HANDLE_OFFSET;
+        let angle = entity.rotation + (PI * 0.5);
+        VecTwo::new(
+            entity.pos.x + f64::cos(angle) * dist,
+            entity.pos.y + f64::sin(angle) * dist,
+        )
+    }
+
+    /// World position of the resize handle for an entity
+    pub fn resize_handle(entity: &Entity) -> VecTwo {
+        let half = entity.size * 0.5;
+        VecTwo::new(entity.pos.x + half, entity.pos.y + half)
+    }
+
+    fn add(&mut self, level: &mut Level, kind: EntityKind, mouse: VecTwo) -> bool {
+        let mut pos = mouse;
+        if self.snap {
+            pos = VecTwo::new(
+                snap_to(pos.x, self.grid_size),
+                snap_to(pos.y, self.grid_size),
+            );
+        }
+
+        let list = level.entities_mut(kind);
+        list.push(Entity::new(pos));
+        self.selected = Some(EntityRef {
+            kind,
+            index: list.len() - 1,
+        });
+
+        true
+    }
+
+    fn begin_drag(&mut self, level: &Level, mouse: VecTwo) {
+        // handles of the current selection take priority
+        if let Some(sel) = self.selected {
+            let entity = &level.entities(sel.kind)[sel.index];
+
+            if distance(mouse, Editor::rotate_handle(entity)) <= HANDLE_RADIUS {
+                self.drag = Some(Drag::Rotate);
+                self.drag_start = Some(entity.clone());
+                return;
+            }
+
+            if distance(mouse, Editor::resize_handle(entity)) <= HANDLE_RADIUS {
+                self.drag = Some(Drag::Resize);
+                self.drag_start = Some(entity.clone());
+                return;
+            }
+        }
+
+        self.selected = pick(level, mouse);
+        self.drag = self.selected.map(|sel| {
+            let entity = &level.entities(sel.kind)[sel.index];
+            Drag::Move {
+                offset: VecTwo::new(mouse.x - entity.pos.x, mouse.y - entity.pos.y),
+            }
+        });
+        self.drag_start = self
+            .selected
+            .map(|sel| level.entities(sel.kind)[sel.index].clone());
+    }
+}
+
+fn same_placement(a: &Entity, b: &Entity) -> bool {
+    a.pos.x == b.pos.x && a.pos.y == b.pos.y && a.rotation == b.rotation && a.size == b.size
+}
+
+/// Topmost entity under the point. Circles render after squares so they are checked first.
+pub fn pick(level: &Level, point: VecTwo) -> Option<EntityRef> {
+    for kind in [EntityKind::Circle, EntityKind::Square] {
+        let list = level.entities(kind);
+        for index in (0..list.len()).rev() {
+            if list[index].contains(kind, point) {
+                return Some(EntityRef { kind, index });
+            }
+        }
+    }
+
+    None
+}
+
+/// Does the entity overlap another entity, a wall, an obstacle or the arena edge
+pub fn is_overlapping(level: &Level, sel: EntityRef) -> bool {
+    let Some(entity) = level.entities(sel.kind).get(sel.index) else {
+        return false;
+    };
+    let collider = Collider::for_entity(sel.kind, entity.size, entity.rotation);
+
+    if collide_static(entity.pos, &collider, &level.walls, &level.obstacles).is_some() {
+        return true;
+    }
+
+    for kind in [EntityKind::Square, EntityKind::Circle] {
+        for (index, other) in level.entities(kind).iter().enumerate() {
+            if kind == sel.kind && index == sel.index {
+                continue;
+            }
+
+            let other_collider = Collider::for_entity(kind, other.size, other.rotation);
+            if overlaps(entity.pos, &collider, other.pos, &other_collider) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+fn snap_to(v: f64, step: f64) -> f64 {
+    if step <= 0.0 {
+        return v;
+    }
+    (v / step).round() * step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(mouse: VecTwo, press: bool, held: bool) -> EditorInput {
+        EditorInput {
+            mouse,
+            press,
+            held,
+            delete: false,
+        }
+    }
+
+    fn editor_with_square() -> (Editor, Level) {
+        let mut editor = Editor::new();
+        editor.enabled = true;
+        let mut level = Level::new();
+        level.squares.push(Entity::new(VecTwo::new(0.0, 0.0)));
+        (editor, level)
+    }
+
+    #[test]
+    fn click_select_is_not_an_edit() {
+        let (mut editor, mut level) = editor_with_square();
+        let mouse = VecTwo::new(2.0, 2.0);
+
+        assert!(!editor.update(&mut level, &input(mouse, true, true)));
+        assert!(!editor.update(&mut level, &input(mouse, false, true)));
+        assert!(!editor.update(&mut level, &input(mouse, false, false)));
+        assert_eq!(
+            editor.selected,
+            Some(EntityRef {
+                kind: EntityKind::Square,
+                index: 0
+            })
+        );
+    }
+
+    #[test]
+    fn drag_is_an_edit() {
+        let (mut editor, mut level) = editor_with_square();
+
+        editor.update(&mut level, &input(VecTwo::new(2.0, 2.0), true, true));
+        editor.update(&mut level, &input(VecTwo::new(12.0, 2.0), false, true));
+        assert!(editor.update(&mut level, &input(VecTwo::new(12.0, 2.0), false, false)));
+        assert_eq!(level.squares[0].pos.x, 10.0);
+    }
+
+    #[test]
+    fn drag_back_to_the_start_is_not_an_edit() {
+        let (mut editor, mut level) = editor_with_square();
+
+        editor.update(&mut level, &input(VecTwo::new(2.0, 2.0), true, true));
+        editor.update(&mut level, &input(VecTwo::new(12.0, 2.0), false, true));
+        editor.update(&mut level, &input(VecTwo::new(2.0, 2.0), false, true));
+        assert!(!editor.update(&mut level, &input(VecTwo::new(2.0, 2.0), false, false)));
+    }
+}
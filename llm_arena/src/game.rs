@@ -5,7 +5,9 @@ use elara_engine::{
     input::*,
     platform_api::*,
     rect::*,
-    render::{light::*, load_image_cursor, material::*, render_command::*, render_pack::*},
+    render::{
+        image::Image, light::*, load_image_cursor, material::*, render_command::*, render_pack::*,
+    },
     state::State as EngineState,
     time::*,
     transform::*,
@@ -17,25 +19,108 @@ use elara_render_opengl::*;
 use std::{
     collections::HashMap,
     ffi::c_void,
+    path::Path,
     sync::{LazyLock, Mutex},
 };
 use tokio::runtime::Runtime;
 
 pub mod ai_level_gen;
+pub mod diagnostics;
+pub mod editor;
+pub mod level;
+pub mod physics;
+pub mod rng;
+pub mod sim;
 pub mod state;
 
 use ai_level_gen::*;
 use assets::*;
+use diagnostics::*;
+use editor::*;
+use level::*;
+use rng::*;
+use sim::*;
 
-const GEN_RANGE: f64 = 300.0;
+/// Screen space layout of the ui panels, laid out left to right
+const PANEL_WIDTH: f64 = 300.0;
+const PANEL_HEIGHT: f64 = 500.0;
+const PANEL_GAP: f64 = 10.0;
+const PANEL_COUNT: i32 = 5;
+
+/// Matches run by the in game balance test
+const BALANCE_TEST_MATCHES: u32 = 200;
+
+/// How many history entries are listed at once
+const HISTORY_VISIBLE: usize = 8;
+
+/// How many of the latest tool calls are listed
+const TOOL_LOG_VISIBLE: usize = 5;
+
+/// Debug overlay text sits under the panels, one line every this many pixels
+const DEBUG_OVERLAY_LINE_HEIGHT: f64 = 18.0;
 
 #[derive(Debug)]
 pub struct LevelGenerationStatus {
-    status: Option<Result<LevelGenResponse, AIError>>,
+    status: Option<Result<LevelGenOutput, AIError>>,
+    validation: Option<ValidationReport>,
+    judge: Option<Result<JudgeOutput, AIError>>,
+
+    /// History entry the judge scored. The score is only shown while that entry is current.
+    judge_index: Option<usize>,
+
+    /// Judge Level request running on a worker thread
+    judging: bool,
+
+    fidelity: Option<FidelityReport>,
+    tool_log: Vec<ToolLogEntry>,
+    consistency: Option<Consistency>,
+
+    /// Run Classification request running on a worker thread
+    generating: bool,
+
+    /// Finished request waiting to be placed on the frame thread
+    generated: Option<Generated>,
+}
+
+/// Model output from the generation worker. Placing it needs the game state,
+/// so that happens on the frame thread.
+#[derive(Debug)]
+pub enum Generated {
+    Classify {
+        prompt: String,
+        seed: u64,
+        output: Result<LevelGenOutput, AIError>,
+        consistency: Option<Consistency>,
+    },
+    Tools {
+        prompt: String,
+        output: Result<ToolGenOutput, AIError>,
+    },
+}
+
+/// Balance test running on a worker thread, or its report once done
+pub enum BalanceStatus {
+    Running { matches: u32 },
+    Done(BatchReport),
 }
 
-pub static AI_GEN_STATUS: LazyLock<Mutex<LevelGenerationStatus>> =
-    LazyLock::new(|| Mutex::new(LevelGenerationStatus { status: None }));
+pub static BALANCE_STATUS: LazyLock<Mutex<Option<BalanceStatus>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+pub static AI_GEN_STATUS: LazyLock<Mutex<LevelGenerationStatus>> = LazyLock::new(|| {
+    Mutex::new(LevelGenerationStatus {
+        status: None,
+        validation: None,
+        judge: None,
+        judge_index: None,
+        judging: false,
+        fidelity: None,
+        tool_log: vec![],
+        consistency: None,
+        generating: false,
+        generated: None,
+    })
+});
 
 #[unsafe(no_mangle)]
 pub fn game_init(
@@ -46,6 +131,8 @@ pub fn game_init(
 ) {
     let gs = unsafe { &mut *(game_state_ptr as *mut State) };
 
+    diagnostics::init_tracing();
+
     elara_engine::debug::init_context(
         es.shader_color.clone(),
         es.shader_color_ui,
@@ -55,6 +142,11 @@ pub fn game_init(
 
     load_game_assets(&mut gs.assets.asset_library, render_api);
 
+    // prices can be edited without a rebuild
+    if let Ok(prices) = PriceTable::load(Path::new(PRICE_TABLE_PATH)) {
+        SESSION_USAGE.lock().unwrap().prices = prices;
+    }
+
     gs.image_circle =
         load_image_cursor(include_bytes!("../resources/circle.png"), render_api).unwrap();
 
@@ -171,7 +263,7 @@ pub fn game_loop(
 
     // ui stuff
     {
-        let r = Rect::new_top_size(VecTwo::new(0.0, 0.0), 300.0, 500.0);
+        let r = panel_rect(0);
         ui::begin(r, &mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
         {
             ui::input_field(
@@ -189,48 +281,368 @@ pub fn game_loop(
 
             ui_frame_state.cursor.y += 80.0;
 
+            let policy_label = match gs.validation_limits.policy {
+                ValidationPolicy::Clamp => "Validation: Clamp",
+                ValidationPolicy::Reject => "Validation: Reject",
+            };
             if ui::button(
-                "Run Classification",
+                policy_label,
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                gs.validation_limits.policy = match gs.validation_limits.policy {
+                    ValidationPolicy::Clamp => ValidationPolicy::Reject,
+                    ValidationPolicy::Reject => ValidationPolicy::Clamp,
+                };
+            }
+
+            let mode_label = match gs.generation_mode {
+                GenerationMode::Classify => "Mode: Classify",
+                GenerationMode::Tools => "Mode: Tool Calls",
+            };
+            if ui::button(
+                mode_label,
                 &mut ui_frame_state,
                 std::line!(),
                 gs.ui_context.as_mut().unwrap(),
             ) {
-                let rt = Runtime::new().unwrap();
-                rt.block_on(async {
-                    let resp = classify(&gs.prompt).await;
+                gs.generation_mode = match gs.generation_mode {
+                    GenerationMode::Classify => GenerationMode::Tools,
+                    GenerationMode::Tools => GenerationMode::Classify,
+                };
+            }
+
+            // tool calls always need the model
+            if gs.generation_mode == GenerationMode::Classify {
+                let backend_label = match gs.classify_config.backend {
+                    Backend::OpenAI => "Backend: OpenAI",
+                    Backend::Offline => "Backend: Offline Rules",
+                };
+                if ui::button(
+                    backend_label,
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    gs.classify_config.backend = match gs.classify_config.backend {
+                        Backend::OpenAI => Backend::Offline,
+                        Backend::Offline => Backend::OpenAI,
+                    };
+                }
+            }
 
-                    if let Ok(resp) = &resp {
-                        gs.squares.clear();
-                        gs.circles.clear();
+            // prompt template
+            {
+                ui::input_field(
+                    "Template File",
+                    "template_path",
+                    &mut gs.template_path,
+                    VecTwo::new(10.0, ui_frame_state.cursor.y + 40.0),
+                    280.0,
+                    &gs.font_style_body.clone(),
+                    &gs.font_style_body.clone(),
+                    &mut ui_frame_state,
+                    gs.ui_context.as_mut().unwrap(),
+                    std::line!(),
+                );
+
+                ui_frame_state.cursor.y += 80.0;
 
-                        for c in 0..resp.square_count {
-                            let r = GEN_RANGE * f64::sqrt((platform_api.rand)());
-                            let theta = (platform_api.rand)() * 2.0 * 3.14159;
+                if ui::button(
+                    &format!("Load Template (using {})", gs.classify_config.template.id),
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    gs.level_file_status =
+                        Some(match PromptTemplate::load(Path::new(&gs.template_path)) {
+                            Ok(template) => {
+                                gs.classify_config.template = template;
+                                format!("Loaded template {}", gs.classify_config.template.id)
+                            }
+                            Err(error) => format!("Error loading template {:?}", error),
+                        });
+                }
+            }
 
-                            let rand_pos = VecTwo::new(r * f64::cos(theta), r * f64::sin(theta));
+            // few shot examples
+            {
+                if ui::button(
+                    &gs.classify_config.example_selection.label(),
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    gs.classify_config.example_selection =
+                        match gs.classify_config.example_selection {
+                            ExampleSelection::None => ExampleSelection::TopK(3),
+                            ExampleSelection::TopK(_) => ExampleSelection::Random(3),
+                            ExampleSelection::Random(_) => ExampleSelection::All,
+                            ExampleSelection::All => ExampleSelection::None,
+                        };
+                }
+            }
 
-                            gs.squares.push(rand_pos);
-                        }
+            // self consistency sampling
+            {
+                if ui::button(
+                    &format!("Samples: {}", gs.consistency_samples),
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    let i = CONSISTENCY_SAMPLE_OPTIONS
+                        .iter()
+                        .position(|s| *s == gs.consistency_samples)
+                        .unwrap_or(0);
+                    gs.consistency_samples =
+                        CONSISTENCY_SAMPLE_OPTIONS[(i + 1) % CONSISTENCY_SAMPLE_OPTIONS.len()];
+                }
 
-                        for c in 0..resp.circle_count {
-                            let r = GEN_RANGE * f64::sqrt((platform_api.rand)());
-                            let theta = (platform_api.rand)() * 2.0 * 3.14159;
+                if gs.consistency_samples > 1 {
+                    let aggregation_label = match gs.aggregation {
+                        Aggregation::MajorityVote => "Combine: Majority Vote",
+                        Aggregation::Median => "Combine: Median",
+                    };
+                    if ui::button(
+                        aggregation_label,
+                        &mut ui_frame_state,
+                        std::line!(),
+                        gs.ui_context.as_mut().unwrap(),
+                    ) {
+                        gs.aggregation = match gs.aggregation {
+                            Aggregation::MajorityVote => Aggregation::Median,
+                            Aggregation::Median => Aggregation::MajorityVote,
+                        };
+                    }
+                }
+            }
 
-                            let rand_pos = VecTwo::new(r * f64::cos(theta), r * f64::sin(theta));
+            let judge_label = if gs.judge_enabled {
+                "Judge: On"
+            } else {
+                "Judge: Off"
+            };
+            if ui::button(
+                judge_label,
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                gs.judge_enabled = !gs.judge_enabled;
+            }
 
-                            gs.circles.push(rand_pos);
+            // rescore the current level, including any edits. Levels from a code have no prompt.
+            if let Some(source) = gs.level_source.as_ref().filter(|s| !s.prompt.is_empty()) {
+                let judging = AI_GEN_STATUS.lock().unwrap().judging;
+                if ui::button(
+                    "Judge Level",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) && !judging
+                {
+                    spawn_judge(source.prompt.clone(), gs);
+                }
+            }
+
+            let generated = AI_GEN_STATUS.lock().unwrap().generated.take();
+            if let Some(generated) = generated {
+                place_generated(generated, gs);
+            }
+
+            let generating = AI_GEN_STATUS.lock().unwrap().generating;
+            if ui::button(
+                "Run Classification",
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) && !generating
+            {
+                if LevelCode::is_level_code(&gs.prompt) {
+                    // rebuild the level from the code without calling the model
+                    match LevelCode::decode(&gs.prompt) {
+                        Ok(code) => {
+                            let mut output = LevelGenOutput {
+                                response: code.response.clone(),
+                                raw_response: serde_json::to_string(&code.response).unwrap(),
+                                model: LEVEL_CODE_MODEL.to_string(),
+                                template_id: String::new(),
+                                usage: Usage::default(),
+                            };
+                            let prompt = gs.prompt.clone();
+                            let validation =
+                                place_output(&mut output, &prompt, code.prompt_hash, code.seed, gs);
+
+                            *AI_GEN_STATUS.lock().unwrap() = LevelGenerationStatus {
+                                status: Some(Ok(output)),
+                                validation: Some(validation),
+                                judge: None,
+                                judge_index: None,
+                                judging: false,
+                                fidelity: None,
+                                tool_log: vec![],
+                                consistency: None,
+                                generating: false,
+                                generated: None,
+                            };
+                        }
+                        Err(error) => {
+                            gs.level_file_status = Some(format!("Invalid level code {:?}", error));
                         }
                     }
+                } else {
+                    let prompt = gs.prompt.clone();
+                    let seed = ((platform_api.rand)() * u32::MAX as f64) as u64;
+                    let mode = gs.generation_mode;
+                    let config = gs.classify_config.clone();
+                    let limits = gs.validation_limits.clone();
+                    let samples = gs.consistency_samples;
+                    let aggregation = gs.aggregation;
+                    AI_GEN_STATUS.lock().unwrap().generating = true;
 
-                    *AI_GEN_STATUS.lock().unwrap() = LevelGenerationStatus { status: Some(resp) };
-                });
+                    // retries and backoff can take minutes, keep them off the frame thread
+                    std::thread::spawn(move || {
+                        let rt = Runtime::new().unwrap();
+                        let generated = rt.block_on(async move {
+                            match mode {
+                                GenerationMode::Classify => {
+                                    let (output, consistency) = if samples > 1 {
+                                        match classify_consistent(
+                                            &prompt,
+                                            &config,
+                                            Some(seed),
+                                            samples,
+                                            aggregation,
+                                        )
+                                        .await
+                                        {
+                                            Ok((output, agreement)) => {
+                                                (Ok(output), Some(agreement))
+                                            }
+                                            Err(error) => (Err(error), None),
+                                        }
+                                    } else {
+                                        (classify(&prompt, &config, Some(seed)).await, None)
+                                    };
+                                    Generated::Classify {
+                                        prompt,
+                                        seed,
+                                        output,
+                                        consistency,
+                                    }
+                                }
+                                GenerationMode::Tools => {
+                                    let output = generate_with_tools(&prompt, &limits).await;
+                                    Generated::Tools { prompt, output }
+                                }
+                            }
+                        });
+
+                        AI_GEN_STATUS.lock().unwrap().generated = Some(generated);
+                    });
+                }
+            }
+
+            // save / load
+            {
+                ui::input_field(
+                    "Level File",
+                    "level_path",
+                    &mut gs.level_path,
+                    VecTwo::new(10.0, ui_frame_state.cursor.y + 40.0),
+                    280.0,
+                    &gs.font_style_body.clone(),
+                    &gs.font_style_body.clone(),
+                    &mut ui_frame_state,
+                    gs.ui_context.as_mut().unwrap(),
+                    std::line!(),
+                );
+
+                ui_frame_state.cursor.y += 80.0;
+
+                if ui::button(
+                    "Save Level",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    gs.level_file_status = Some(match &gs.level_source {
+                        Some(source) => {
+                            match save_level(Path::new(&gs.level_path), source, &gs.level) {
+                                Ok(()) => format!("Saved {}", gs.level_path),
+                                Err(error) => format!("Error saving level {:?}", error),
+                            }
+                        }
+                        None => "No generated level to save".to_string(),
+                    });
+                }
+
+                if ui::button(
+                    "Load Level",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    let loaded = load_level(Path::new(&gs.level_path), &gs.validation_limits);
+                    gs.level_file_status = Some(match loaded {
+                        Ok((source, level, report)) => {
+                            gs.prompt = source.prompt.clone();
+                            gs.level = level;
+                            gs.level_source = Some(source);
+                            gs.level_code = None;
+                            push_history(gs);
+                            if report.is_clean() {
+                                format!("Loaded {}", gs.level_path)
+                            } else {
+                                format!(
+                                    "Loaded {}, fixed {} issues. {}",
+                                    gs.level_path,
+                                    report.issues.len(),
+                                    report.issues.join(". ")
+                                )
+                            }
+                        }
+                        Err(error) => format!("Error loading level {:?}", error),
+                    });
+                }
+
+                if let Some(code) = &gs.level_code {
+                    ui::text(
+                        &format!("Level code {}", code.encode()),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+
+                if let Some(file_status) = &gs.level_file_status {
+                    ui::text(
+                        file_status,
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
             }
 
             if let Ok(status) = AI_GEN_STATUS.lock() {
                 if let Some(resp) = &status.status {
                     match resp {
-                        Ok(level_gen_data) => {
-                            if level_gen_data.valid {
+                        Ok(output) => {
+                            let level_gen_data = &output.response;
+                            let rejected = status
+                                .validation
+                                .as_ref()
+                                .map(|v| v.rejected)
+                                .unwrap_or(false);
+
+                            if rejected {
+                                ui::text(
+                                    "Level rejected by validation",
+                                    &mut ui_frame_state,
+                                    &mut gs.ui_context.as_mut().unwrap(),
+                                );
+                            } else if level_gen_data.valid {
                                 ui::text(
                                     "Level Successfully Generated",
                                     &mut ui_frame_state,
@@ -245,6 +657,14 @@ pub fn game_loop(
                             }
                         }
 
+                        Err(AIError::SpendingCap { spent, cap }) => {
+                            ui::text(
+                                &format!("Spending cap reached, ${:.4} of ${:.2}", spent, cap),
+                                &mut ui_frame_state,
+                                &mut gs.ui_context.as_mut().unwrap(),
+                            );
+                        }
+
                         Err(error) => {
                             ui::text(
                                 "Error getting response",
@@ -255,6 +675,154 @@ pub fn game_loop(
                     }
                 }
 
+                if let Some(Ok(output)) = &status.status {
+                    ui::text(
+                        &format!("Request {}", output.usage.describe()),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+
+                    if output.model == OFFLINE_MODEL
+                        && gs.classify_config.backend != Backend::Offline
+                    {
+                        ui::text(
+                            "Model unavailable, classified with offline rules",
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                }
+
+                if status.generating {
+                    ui::text(
+                        "Generating level...",
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+
+                // read live so requests in flight and backoff show while they happen
+                for limit in rate_limit_status() {
+                    ui::text(
+                        &limit.describe(),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+
+                if let Some(report) = &status.validation {
+                    for issue in &report.issues {
+                        ui::text(
+                            &format!("- {}", issue),
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                }
+
+                if let Some(consistency) = &status.consistency {
+                    ui::text(
+                        &format!(
+                            "Confidence {:.0}%, {} of {} samples agree",
+                            consistency.ratio() * 100.0,
+                            consistency.agreeing,
+                            consistency.samples
+                        ),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+
+                    if consistency.failed > 0 {
+                        ui::text(
+                            &format!("{} samples failed", consistency.failed),
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                }
+
+                if !status.tool_log.is_empty() {
+                    ui::text(
+                        &format!("{} tool calls", status.tool_log.len()),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+
+                    // only the last few calls fit
+                    let start = status.tool_log.len().saturating_sub(TOOL_LOG_VISIBLE);
+                    for entry in &status.tool_log[start..] {
+                        ui::text(
+                            &entry.label(),
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                }
+
+                if let Some(fidelity) = &status.fidelity {
+                    for check in &fidelity.checks {
+                        let result = if check.passed() { "Pass" } else { "Fail" };
+                        ui::text(
+                            &format!(
+                                "{}: {} (got {})",
+                                result,
+                                check.expectation.describe(),
+                                check.detail
+                            ),
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                }
+
+                if status.judging {
+                    ui::text(
+                        "Judging level...",
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+
+                let current_judge = status
+                    .judge
+                    .as_ref()
+                    .filter(|_| status.judge_index == gs.history.current_index());
+                match current_judge {
+                    Some(Ok(judged)) => {
+                        ui::text(
+                            &format!(
+                                "Judge score {}/{}, {}",
+                                judged.response.score,
+                                JUDGE_MAX_SCORE,
+                                judged.usage.describe()
+                            ),
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                        ui::text(
+                            &judged.response.critique,
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                    Some(Err(_)) => {
+                        ui::text(
+                            "Error judging level",
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                    None => {}
+                }
+
+                for warning in &gs.level_warnings {
+                    ui::text(
+                        &format!("Warning: {}", warning),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+
                 ui::text(
                     &format!("{:?}", status),
                     &mut ui_frame_state,
@@ -266,35 +834,708 @@ pub fn game_loop(
         ui::end(&mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
     }
 
+    // history
+    {
+        // shortcuts, ignored while typing
+        let typing = gs
+            .ui_context
+            .as_ref()
+            .unwrap()
+            .selected_input_field
+            .is_some();
+        if !typing && input.keyboard.get_key(KeyCode::Control).pressing {
+            let shift = input.keyboard.get_key(KeyCode::Shift).pressing;
+
+            if input.keyboard.get_key(KeyCode::Z).on_press {
+                let entry = if shift {
+                    gs.history.redo().cloned()
+                } else {
+                    gs.history.undo().cloned()
+                };
+
+                if let Some(entry) = entry {
+                    restore_history(entry, gs);
+                }
+            }
+
+            if input.keyboard.get_key(KeyCode::Y).on_press {
+                if let Some(entry) = gs.history.redo().cloned() {
+                    restore_history(entry, gs);
+                }
+            }
+        }
+
+        let r = panel_rect(1);
+        ui::begin(r, &mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+        {
+            if ui::button(
+                "Undo",
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                if let Some(entry) = gs.history.undo().cloned() {
+                    restore_history(entry, gs);
+                }
+            }
+
+            if ui::button(
+                "Redo",
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                if let Some(entry) = gs.history.redo().cloned() {
+                    restore_history(entry, gs);
+                }
+            }
+
+            // newest first, scrolled by HISTORY_VISIBLE at a time
+            let count = gs.history.entries().len();
+            gs.history_scroll = gs.history_scroll.min(count.saturating_sub(1));
+
+            if gs.history_scroll > 0
+                && ui::button(
+                    "Newer",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                )
+            {
+                gs.history_scroll = gs.history_scroll.saturating_sub(HISTORY_VISIBLE);
+            }
+
+            let mut selected: Option<usize> = None;
+            for i in (0..count)
+                .rev()
+                .skip(gs.history_scroll)
+                .take(HISTORY_VISIBLE)
+            {
+                let entry = &gs.history.entries()[i];
+
+                let mut label = entry.label();
+                if gs.history.current_index() == Some(i) {
+                    label = format!("> {}", label);
+                }
+
+                // scale the line so entry ids can't collide with other buttons
+                if ui::button(
+                    &label,
+                    &mut ui_frame_state,
+                    std::line!() * 1000 + i as u32,
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    selected = Some(i);
+                }
+            }
+
+            if let Some(i) = selected {
+                if let Some(entry) = gs.history.jump_to(i).cloned() {
+                    restore_history(entry, gs);
+                }
+            }
+
+            if gs.history_scroll + HISTORY_VISIBLE < count
+                && ui::button(
+                    "Older",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                )
+            {
+                gs.history_scroll += HISTORY_VISIBLE;
+            }
+        }
+        ui::end(&mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+    }
+
+    // editor
+    {
+        let r = panel_rect(2);
+        ui::begin(r, &mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+        {
+            let label = if gs.editor.enabled {
+                "Editor: On"
+            } else {
+                "Editor: Off"
+            };
+            if ui::button(
+                label,
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                gs.editor.enabled = !gs.editor.enabled;
+            }
+
+            if gs.editor.enabled {
+                for (tool, label, line) in [
+                    (EditorTool::Select, "Select", std::line!()),
+                    (EditorTool::AddSquare, "Add Square", std::line!()),
+                    (EditorTool::AddCircle, "Add Circle", std::line!()),
+                ] {
+                    let label = if gs.editor.tool == tool {
+                        format!("> {}", label)
+                    } else {
+                        label.to_string()
+                    };
+                    if ui::button(
+                        &label,
+                        &mut ui_frame_state,
+                        line,
+                        gs.ui_context.as_mut().unwrap(),
+                    ) {
+                        gs.editor.tool = tool;
+                    }
+                }
+
+                let label = if gs.editor.snap {
+                    "Snap: On"
+                } else {
+                    "Snap: Off"
+                };
+                if ui::button(
+                    label,
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    gs.editor.snap = !gs.editor.snap;
+                }
+
+                if ui::button(
+                    "Delete Selected",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    if let Some(sel) = gs.editor.selected.take() {
+                        if can_edit(gs) && sel.index < gs.level.entities(sel.kind).len() {
+                            gs.level.entities_mut(sel.kind).remove(sel.index);
+                            finish_edit(gs);
+                        }
+                    }
+                }
+
+                if let Some(sel) = gs.editor.selected {
+                    if is_overlapping(&gs.level, sel) {
+                        ui::text(
+                            "Selected entity overlaps",
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+                }
+
+                ui::text(
+                    "Drag to move. Top handle rotates, corner handle resizes.",
+                    &mut ui_frame_state,
+                    &mut gs.ui_context.as_mut().unwrap(),
+                );
+            }
+        }
+        ui::end(&mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+
+        let typing = gs
+            .ui_context
+            .as_ref()
+            .unwrap()
+            .selected_input_field
+            .is_some();
+        let over_ui = mouse_over_ui(input.mouse.pos);
+        let editor_input = EditorInput {
+            mouse: mouse_world_pos(input.mouse.pos, es),
+            press: !over_ui && input.mouse.button_left.on_press,
+            held: input.mouse.button_left.pressing,
+            delete: !typing && input.keyboard.get_key(KeyCode::Delete).on_press,
+        };
+
+        if can_edit(gs) && gs.editor.update(&mut gs.level, &editor_input) {
+            finish_edit(gs);
+        }
+    }
+
+    // match
+    {
+        let r = panel_rect(3);
+        ui::begin(r, &mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+        {
+            if ui::button(
+                "Start Match",
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                let seed = ((platform_api.rand)() * u32::MAX as f64) as u64;
+                gs.sim = Some(Sim::new(&gs.level, gs.sim_config.clone(), seed));
+                gs.sim_accumulator = 0.0;
+                gs.match_level = gs.level.clone();
+                gs.replay_player = None;
+            }
+
+            if gs.sim.is_some()
+                && ui::button(
+                    "Stop Match",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                )
+            {
+                gs.sim = None;
+            }
+
+            let balance_running = matches!(
+                *BALANCE_STATUS.lock().unwrap(),
+                Some(BalanceStatus::Running { .. })
+            );
+            if ui::button(
+                "Balance Test",
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) && !balance_running
+            {
+                let batch = BatchConfig {
+                    matches: BALANCE_TEST_MATCHES,
+                    base_seed: ((platform_api.rand)() * u32::MAX as f64) as u64,
+                    sim: gs.sim_config.clone(),
+                };
+                let level = gs.level.clone();
+
+                // hundreds of full matches take far longer than a frame
+                *BALANCE_STATUS.lock().unwrap() = Some(BalanceStatus::Running {
+                    matches: batch.matches,
+                });
+                std::thread::spawn(move || {
+                    let (report, _) = run_batch(&level, &batch);
+                    *BALANCE_STATUS.lock().unwrap() = Some(BalanceStatus::Done(report));
+                });
+            }
+
+            match &*BALANCE_STATUS.lock().unwrap() {
+                Some(BalanceStatus::Running { matches }) => {
+                    ui::text(
+                        &format!("Running {} matches...", matches),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+                Some(BalanceStatus::Done(report)) => {
+                    ui::text(
+                        &report.summary(),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+                None => {}
+            }
+
+            // replays
+            {
+                ui::input_field(
+                    "Replay File",
+                    "replay_path",
+                    &mut gs.replay_path,
+                    VecTwo::new(10.0, ui_frame_state.cursor.y + 40.0),
+                    280.0,
+                    &gs.font_style_body.clone(),
+                    &gs.font_style_body.clone(),
+                    &mut ui_frame_state,
+                    gs.ui_context.as_mut().unwrap(),
+                    std::line!(),
+                );
+
+                ui_frame_state.cursor.y += 80.0;
+
+                if let Some(sim) = &gs.sim {
+                    if ui::button(
+                        "Save Replay",
+                        &mut ui_frame_state,
+                        std::line!(),
+                        gs.ui_context.as_mut().unwrap(),
+                    ) {
+                        let source = gs.level_source.clone().unwrap_or_default();
+                        let replay = Replay::from_sim(&gs.match_level, &source, sim);
+                        gs.replay_status =
+                            Some(match save_replay(Path::new(&gs.replay_path), &replay) {
+                                Ok(()) => format!("Saved replay {}", gs.replay_path),
+                                Err(error) => format!("Error saving replay {:?}", error),
+                            });
+                    }
+                }
+
+                if ui::button(
+                    "Load Replay",
+                    &mut ui_frame_state,
+                    std::line!(),
+                    gs.ui_context.as_mut().unwrap(),
+                ) {
+                    let loaded = load_replay(Path::new(&gs.replay_path), &gs.validation_limits);
+                    gs.replay_status = Some(match loaded {
+                        Ok((replay, report)) => {
+                            gs.sim = None;
+                            gs.replay_player = Some(ReplayPlayer::new(replay));
+                            if report.is_clean() {
+                                format!("Loaded replay {}", gs.replay_path)
+                            } else {
+                                format!(
+                                    "Loaded replay {}, fixed {} issues. {}",
+                                    gs.replay_path,
+                                    report.issues.len(),
+                                    report.issues.join(". ")
+                                )
+                            }
+                        }
+                        Err(error) => format!("Error loading replay {:?}", error),
+                    });
+                }
+
+                if let Some(player) = &mut gs.replay_player {
+                    let label = if player.paused { "Play" } else { "Pause" };
+                    if ui::button(
+                        label,
+                        &mut ui_frame_state,
+                        std::line!(),
+                        gs.ui_context.as_mut().unwrap(),
+                    ) {
+                        player.paused = !player.paused;
+                    }
+
+                    // scrub by whole seconds
+                    let second = (1.0 / SIM_TIMESTEP) as u64;
+                    let tick = player.sim.tick;
+                    for (label, line, target) in [
+                        ("Restart", std::line!(), 0),
+                        ("Back 5s", std::line!(), tick.saturating_sub(second * 5)),
+                        ("Back 1 Tick", std::line!(), tick.saturating_sub(1)),
+                        ("Forward 1 Tick", std::line!(), tick + 1),
+                        ("Forward 5s", std::line!(), tick + second * 5),
+                    ] {
+                        if ui::button(
+                            label,
+                            &mut ui_frame_state,
+                            line,
+                            gs.ui_context.as_mut().unwrap(),
+                        ) {
+                            player.seek(target);
+                        }
+                    }
+
+                    let speed_label = format!("Speed {}x", player.speed);
+                    if ui::button(
+                        &speed_label,
+                        &mut ui_frame_state,
+                        std::line!(),
+                        gs.ui_context.as_mut().unwrap(),
+                    ) {
+                        player.speed = match player.speed {
+                            s if s < 1.0 => 1.0,
+                            s if s < 2.0 => 2.0,
+                            s if s < 4.0 => 4.0,
+                            _ => 0.25,
+                        };
+                    }
+
+                    ui::text(
+                        &format!("Tick {} / {}", player.sim.tick, player.replay.ticks),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+
+                    if let Some(tick) = player.desync {
+                        ui::text(
+                            &format!("Replay desynced from the recording at tick {}", tick),
+                            &mut ui_frame_state,
+                            &mut gs.ui_context.as_mut().unwrap(),
+                        );
+                    }
+
+                    if ui::button(
+                        "Close Replay",
+                        &mut ui_frame_state,
+                        std::line!(),
+                        gs.ui_context.as_mut().unwrap(),
+                    ) {
+                        gs.replay_player = None;
+                    }
+                }
+
+                if let Some(replay_status) = &gs.replay_status {
+                    ui::text(
+                        replay_status,
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    );
+                }
+            }
+
+            let shown_sim = match &gs.replay_player {
+                Some(player) => Some(&player.sim),
+                None => gs.sim.as_ref(),
+            };
+            if let Some(sim) = shown_sim {
+                ui::text(
+                    &sim.rules.describe(),
+                    &mut ui_frame_state,
+                    &mut gs.ui_context.as_mut().unwrap(),
+                );
+
+                let time = match sim.time_left() {
+                    Some(left) => format!("Time left {:.1}s", left),
+                    None => format!("Time {:.1}s", sim.time()),
+                };
+                ui::text(
+                    &format!(
+                        "{}  Squares {}  Circles {}",
+                        time,
+                        sim.alive_count(EntityKind::Square),
+                        sim.alive_count(EntityKind::Circle)
+                    ),
+                    &mut ui_frame_state,
+                    &mut gs.ui_context.as_mut().unwrap(),
+                );
+
+                ui::text(
+                    &format!(
+                        "Kills  Squares {}  Circles {}",
+                        sim.square_kills, sim.circle_kills
+                    ),
+                    &mut ui_frame_state,
+                    &mut gs.ui_context.as_mut().unwrap(),
+                );
+
+                match sim.outcome {
+                    Some(SimOutcome::Winner(team)) => ui::text(
+                        &format!("{} win", team_name(team)),
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    ),
+                    Some(SimOutcome::Draw) => ui::text(
+                        "Draw",
+                        &mut ui_frame_state,
+                        &mut gs.ui_context.as_mut().unwrap(),
+                    ),
+                    None => {}
+                }
+            }
+        }
+        ui::end(&mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+    }
+
+    // usage
+    {
+        let r = panel_rect(4);
+        ui::begin(r, &mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+        {
+            let mut usage = SESSION_USAGE.lock().unwrap();
+
+            let cap_label = match usage.spending_cap {
+                Some(cap) => format!("Spending Cap: ${:.2}", cap),
+                None => "Spending Cap: None".to_string(),
+            };
+            if ui::button(
+                &cap_label,
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                let i = SPENDING_CAP_OPTIONS
+                    .iter()
+                    .position(|c| *c == usage.spending_cap)
+                    .unwrap_or(0);
+                usage.spending_cap = SPENDING_CAP_OPTIONS[(i + 1) % SPENDING_CAP_OPTIONS.len()];
+            }
+
+            ui::text(
+                &format!("Session {} calls", usage.records.len()),
+                &mut ui_frame_state,
+                &mut gs.ui_context.as_mut().unwrap(),
+            );
+            ui::text(
+                &usage.total().describe(),
+                &mut ui_frame_state,
+                &mut gs.ui_context.as_mut().unwrap(),
+            );
+
+            for (model, model_usage) in usage.by_model() {
+                let unpriced = if usage.prices.is_priced(&model) {
+                    ""
+                } else {
+                    " (unpriced)"
+                };
+                ui::text(
+                    &format!("{} {}{}", model, model_usage.describe(), unpriced),
+                    &mut ui_frame_state,
+                    &mut gs.ui_context.as_mut().unwrap(),
+                );
+            }
+
+            if usage.check_cap().is_err() {
+                ui::text(
+                    "Spending cap reached, new calls are blocked",
+                    &mut ui_frame_state,
+                    &mut gs.ui_context.as_mut().unwrap(),
+                );
+            }
+            drop(usage);
+
+            let overlay_label = if gs.debug_overlay {
+                "Debug Overlay: On"
+            } else {
+                "Debug Overlay: Off"
+            };
+            if ui::button(
+                overlay_label,
+                &mut ui_frame_state,
+                std::line!(),
+                gs.ui_context.as_mut().unwrap(),
+            ) {
+                gs.debug_overlay = !gs.debug_overlay;
+            }
+        }
+        ui::end(&mut ui_frame_state, &mut gs.ui_context.as_mut().unwrap());
+    }
+
+    if gs.debug_overlay {
+        draw_debug_overlay(&gs.font_style_body);
+    }
+
+    if let Some(player) = &mut gs.replay_player {
+        player.update(prev_delta_time);
+    }
+
+    // step simulation on a fixed timestep
+    if let Some(sim) = &mut gs.sim {
+        gs.sim_accumulator += prev_delta_time;
+
+        let mut steps = 0;
+        while gs.sim_accumulator >= SIM_TIMESTEP && steps < SIM_MAX_STEPS_PER_FRAME {
+            sim.step();
+            gs.sim_accumulator -= SIM_TIMESTEP;
+            steps += 1;
+        }
+
+        // fell too far behind, drop the backlog instead of spiraling
+        if steps == SIM_MAX_STEPS_PER_FRAME {
+            gs.sim_accumulator = 0.0;
+        }
+    }
+
     // render level
     {
-        // render squares
-        for pos in &gs.squares {
-            let r = Rect::new_center(*pos, VecTwo::new(30.0, 30.0));
+        let _render = tracing::info_span!(RENDER_SPAN).entered();
 
+        // render walls
+        {
             let mut mat = Material::new();
             mat.shader = Some(es.shader_color);
-            mat.set_color(COLOR_WHITE);
+            mat.set_color(Color::new(0.5, 0.5, 0.5, 1.0));
 
-            es.render_system.add_command(
-                RenderCommand::new_rect(&r, -1.0, 0.0, &mat),
-                RenderPackID::World,
-            );
+            for wall in &gs.level.walls {
+                render_segment(wall.start, wall.end, WALL_THICKNESS, &mat, es);
+            }
+
+            // obstacles are drawn as outlines
+            for obstacle in &gs.level.obstacles {
+                for (start, end) in obstacle.edges() {
+                    render_segment(start, end, WALL_THICKNESS, &mat, es);
+                }
+            }
         }
 
-        // render circles
-        for pos in &gs.circles {
-            let r = Rect::new_center(*pos, VecTwo::new(30.0, 30.0));
+        let shown_sim = match &gs.replay_player {
+            Some(player) => Some(&player.sim),
+            None => gs.sim.as_ref(),
+        };
+        if let Some(sim) = shown_sim {
+            // render match units with health bars
+            for unit in &sim.units {
+                let size = unit.radius * 2.0;
+                render_entity(
+                    unit.team,
+                    unit.pos,
+                    size,
+                    unit.rotation,
+                    &gs.image_circle,
+                    es,
+                );
 
-            let mut mat = Material::new();
-            mat.shader = Some(es.color_texture_shader);
-            mat.set_color(COLOR_WHITE);
-            mat.set_image(gs.image_circle.gl_id.unwrap());
+                // replays carry the config they were recorded with
+                let max_health = sim.config().stats(unit.team).health;
+                let fill = (unit.health / max_health).clamp(0.0, 1.0);
+                let r = Rect::new_center(
+                    VecTwo::new(
+                        unit.pos.x - (size * 0.5) + (size * fill * 0.5),
+                        unit.pos.y - (size * 0.5) - 6.0,
+                    ),
+                    VecTwo::new(size * fill, 3.0),
+                );
 
-            es.render_system.add_command(
-                RenderCommand::new_rect(&r, -1.0, 0.0, &mat),
-                RenderPackID::World,
-            );
+                let mut mat = Material::new();
+                mat.shader = Some(es.shader_color);
+                mat.set_color(Color::new(1.0 - fill, fill, 0.0, 1.0));
+
+                es.render_system.add_command(
+                    RenderCommand::new_rect(&r, -1.0, 0.0, &mat),
+                    RenderPackID::World,
+                );
+            }
+        } else {
+            for kind in [EntityKind::Square, EntityKind::Circle] {
+                for entity in gs.level.entities(kind) {
+                    render_entity(
+                        kind,
+                        entity.pos,
+                        entity.size,
+                        entity.rotation,
+                        &gs.image_circle,
+                        es,
+                    );
+                }
+            }
+        }
+
+        // render editor selection
+        if gs.editor.enabled && can_edit(gs) {
+            if let Some(sel) = gs.editor.selected {
+                if let Some(entity) = gs.level.entities(sel.kind).get(sel.index) {
+                    let mut mat = Material::new();
+                    mat.shader = Some(es.shader_color);
+                    if is_overlapping(&gs.level, sel) {
+                        mat.set_color(Color::new(1.0, 0.0, 0.0, 1.0));
+                    } else {
+                        mat.set_color(Color::new(1.0, 0.8, 0.0, 1.0));
+                    }
+
+                    // outline
+                    let half = entity.size * 0.5 + 4.0;
+                    let (sin, cos) = f64::sin_cos(entity.rotation);
+                    let corners: Vec<VecTwo> = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                        .iter()
+                        .map(|(x, y)| {
+                            let x = x * half;
+                            let y = y * half;
+                            VecTwo::new(
+                                entity.pos.x + x * cos - y * sin,
+                                entity.pos.y + x * sin + y * cos,
+                            )
+                        })
+                        .collect();
+                    for i in 0..corners.len() {
+                        let next = (i + 1) % corners.len();
+                        render_segment(corners[i], corners[next], 2.0, &mat, es);
+                    }
+
+                    // handles
+                    for handle in [Editor::rotate_handle(entity), Editor::resize_handle(entity)] {
+                        let r = Rect::new_center(handle, VecTwo::new(10.0, 10.0));
+                        es.render_system.add_command(
+                            RenderCommand::new_rect(&r, -1.0, 0.0, &mat),
+                            RenderPackID::World,
+                        );
+                    }
+                }
+            }
         }
     }
 
@@ -316,3 +1557,287 @@ pub fn game_loop(
     es.game_ui_debug_render_commands = elara_engine::debug::get_ui_render_list().clone();
     es.game_debug_render_commands = elara_engine::debug::get_render_list().clone();
 }
+
+/// Timings and events under the panels, drawn into the ui debug render list
+fn draw_debug_overlay(font_style: &FontStyle) {
+    let lines = DEBUG_LOG.lock().unwrap().lines();
+    for (i, line) in lines.iter().enumerate() {
+        let pos = VecTwo::new(
+            PANEL_GAP,
+            PANEL_HEIGHT + PANEL_GAP + i as f64 * DEBUG_OVERLAY_LINE_HEIGHT,
+        );
+        elara_engine::debug::draw_text(line, pos, COLOR_WHITE, font_style);
+    }
+}
+
+/// Record the current level as the newest history entry
+fn push_history(gs: &mut State) {
+    gs.level_warnings = check_reachability(&gs.level);
+
+    // a new entry can reuse the index of an undone one, so its score doesn't carry over
+    AI_GEN_STATUS.lock().unwrap().judge = None;
+
+    gs.history.push(HistoryEntry {
+        level: gs.level.clone(),
+        source: gs.level_source.clone(),
+        code: gs.level_code.clone(),
+    });
+    gs.history_scroll = 0;
+}
+
+/// Make a history entry the current level
+fn restore_history(entry: HistoryEntry, gs: &mut State) {
+    gs.level = entry.level;
+    gs.level_source = entry.source;
+    gs.level_code = entry.code;
+    gs.level_warnings = check_reachability(&gs.level);
+}
+
+/// Place a finished request from the generation worker, then check and score the level.
+/// Uses the prompt the request was made with, the prompt box may have changed since.
+fn place_generated(generated: Generated, gs: &mut State) {
+    let mut tool_log: Vec<ToolLogEntry> = vec![];
+    let mut consistency: Option<Consistency> = None;
+
+    let (prompt, resp, validation) = match generated {
+        Generated::Classify {
+            prompt,
+            seed,
+            mut output,
+            consistency: agreement,
+        } => {
+            consistency = agreement;
+            let validation = match &mut output {
+                Ok(output) => Some(place_output(
+                    output,
+                    &prompt,
+                    prompt_hash(&prompt),
+                    seed,
+                    gs,
+                )),
+                Err(_) => None,
+            };
+            (prompt, output, validation)
+        }
+        Generated::Tools { prompt, output } => match output {
+            Ok(tools) => {
+                let validation = place_tool_output(&tools, &prompt, gs);
+                tool_log = tools.log.clone();
+                (prompt, Ok(tools.to_level_gen_output()), Some(validation))
+            }
+            Err(error) => (prompt, Err(error), None),
+        },
+    };
+
+    // second pass scoring the placed level
+    let placed = validation.as_ref().map(|v| !v.rejected).unwrap_or(false)
+        && resp.as_ref().map(|o| o.response.valid).unwrap_or(false);
+    let fidelity = resp.as_ref().ok().map(|output| {
+        let level = if placed { Some(&gs.level) } else { None };
+        check_fidelity(&prompt, &output.response, level)
+    });
+
+    *AI_GEN_STATUS.lock().unwrap() = LevelGenerationStatus {
+        status: Some(resp),
+        validation,
+        judge: None,
+        judge_index: None,
+        judging: false,
+        fidelity,
+        tool_log,
+        consistency,
+        generating: false,
+        generated: None,
+    };
+
+    if gs.judge_enabled && placed {
+        spawn_judge(prompt, gs);
+    }
+}
+
+/// Score the current level against the prompt on a worker thread, it's a network call.
+/// The score is kept against the current history entry.
+fn spawn_judge(prompt: String, gs: &State) {
+    let description = gs.level.describe();
+    let index = gs.history.current_index();
+    AI_GEN_STATUS.lock().unwrap().judging = true;
+
+    std::thread::spawn(move || {
+        let rt = Runtime::new().unwrap();
+        let judged = rt.block_on(judge(&prompt, &description));
+
+        let mut status = AI_GEN_STATUS.lock().unwrap();
+        status.judge = Some(judged);
+        status.judge_index = index;
+        status.judging = false;
+    });
+}
+
+/// The level can't be edited mid match or while a replay is playing
+fn can_edit(gs: &State) -> bool {
+    gs.sim.is_none() && gs.replay_player.is_none()
+}
+
+/// A manual edit changed the level
+fn finish_edit(gs: &mut State) {
+    // the level code only describes the model output, not the edits
+    gs.level_code = None;
+    push_history(gs);
+}
+
+fn panel_rect(index: i32) -> Rect {
+    Rect::new_top_size(
+        VecTwo::new(index as f64 * (PANEL_WIDTH + PANEL_GAP), 0.0),
+        PANEL_WIDTH,
+        PANEL_HEIGHT,
+    )
+}
+
+fn mouse_over_ui(mouse: VecTwo) -> bool {
+    mouse.x <= PANEL_COUNT as f64 * (PANEL_WIDTH + PANEL_GAP) && mouse.y <= PANEL_HEIGHT
+}
+
+/// Screen space to world space for the world pack.
+/// The world pack is orthographic, one unit per pixel and centered on the camera.
+fn mouse_world_pos(mouse: VecTwo, es: &mut EngineState) -> VecTwo {
+    let resolution = es.window_resolution;
+    let cam = &es.render_system.get_pack(RenderPackID::World).camera;
+
+    VecTwo::new(
+        mouse.x - (resolution.x * 0.5) + cam.transform.local_position.x,
+        mouse.y - (resolution.y * 0.5) + cam.transform.local_position.y,
+    )
+}
+
+/// Validate the output and place it as the current level
+fn place_output(
+    output: &mut LevelGenOutput,
+    prompt: &str,
+    prompt_hash: u32,
+    seed: u64,
+    gs: &mut State,
+) -> ValidationReport {
+    let report = validate(&mut output.response, &gs.validation_limits);
+
+    // invalid prompts keep the current level
+    if !report.rejected && output.response.valid {
+        let _placement = tracing::info_span!("placement", seed).entered();
+
+        // the prompt box holds the code itself, nothing the judge or fidelity check can score against
+        let prompt = if output.model == LEVEL_CODE_MODEL {
+            String::new()
+        } else {
+            prompt.to_string()
+        };
+
+        let mut rng = Rng::new(seed);
+        gs.level = Level::from_response(&output.response, &mut || rng.next_f64());
+        gs.level_source = Some(LevelSource {
+            prompt,
+            seed,
+            model: output.model.clone(),
+            raw_response: output.raw_response.clone(),
+            template_id: output.template_id.clone(),
+        });
+
+        let code = LevelCode {
+            prompt_hash,
+            seed,
+            response: output.response.clone(),
+        };
+        tracing::info!(code = code.encode(), "Level code");
+        gs.level_code = Some(code);
+
+        push_history(gs);
+    }
+
+    report
+}
+
+/// Use the level the model built with tool calls as the current level
+fn place_tool_output(tools: &ToolGenOutput, prompt: &str, gs: &mut State) -> ValidationReport {
+    // the tools only build within the arena, the limits still apply to what they built
+    let mut response = tools.response.clone();
+    let mut level = tools.level.clone();
+    let mut report = validate(&mut response, &gs.validation_limits);
+    let level_report = validate_level(&mut level, &gs.validation_limits);
+    report.issues.extend(level_report.issues);
+    report.rejected |= level_report.rejected;
+
+    if tools.hit_limit {
+        report.issues.push(format!(
+            "Stopped at the tool limit of {} calls over {} turns",
+            MAX_TOOL_CALLS, MAX_TOOL_TURNS
+        ));
+    }
+
+    if report.rejected || !response.valid {
+        return report;
+    }
+
+    let _placement = tracing::info_span!("placement", mode = "tools").entered();
+
+    gs.level = level;
+    gs.level_source = Some(LevelSource {
+        prompt: prompt.to_string(),
+        seed: 0,
+        model: tools.model.clone(),
+        // the counts stand in for a classification so saved files load the same way
+        raw_response: serde_json::to_string(&response).unwrap(),
+        template_id: String::new(),
+    });
+
+    // share codes place from counts, they can't hold hand placed entities
+    gs.level_code = None;
+
+    push_history(gs);
+
+    report
+}
+
+fn render_entity(
+    kind: EntityKind,
+    pos: VecTwo,
+    size: f64,
+    rotation: f64,
+    image_circle: &Image,
+    es: &mut EngineState,
+) {
+    let r = Rect::new_center(pos, VecTwo::new(size, size));
+
+    let mut mat = Material::new();
+    mat.set_color(COLOR_WHITE);
+    match kind {
+        EntityKind::Square => {
+            mat.shader = Some(es.shader_color);
+        }
+        EntityKind::Circle => {
+            mat.shader = Some(es.color_texture_shader);
+            mat.set_image(image_circle.gl_id.unwrap());
+        }
+    }
+
+    es.render_system.add_command(
+        RenderCommand::new_rect(&r, -1.0, rotation, &mat),
+        RenderPackID::World,
+    );
+}
+
+/// Render a line segment as a rotated rect in the world pack
+fn render_segment(
+    start: VecTwo,
+    end: VecTwo,
+    thickness: f64,
+    mat: &Material,
+    es: &mut EngineState,
+) {
+    let center = VecTwo::new((start.x + end.x) * 0.5, (start.y + end.y) * 0.5);
+    let length = distance(start, end);
+    let rotation = f64::atan2(end.y - start.y, end.x - start.x);
+
+    let r = Rect::new_center(center, VecTwo::new(length, thickness));
+    es.render_system.add_command(
+        RenderCommand::new_rect(&r, -1.0, rotation, mat),
+        RenderPackID::World,
+    );
+}
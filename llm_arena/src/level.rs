@@ -0,0 +1,282 @@
+use crate::{
+    ai_level_gen::*,
+    sim::{compile_rules, team_name},
+};
+use elara_engine::vectors::*;
+use serde::{Deserialize, Serialize};
+
+pub mod geometry;
+pub mod history;
+pub mod navigation;
+pub mod save;
+pub mod share_code;
+
+pub use geometry::*;
+pub use history::*;
+pub use navigation::*;
+pub use save::*;
+pub use share_code::*;
+
+/// Radius of the arena. Entities are placed inside this.
+pub const GEN_RANGE: f64 = 300.0;
+
+/// Width and height of a rendered entity
+pub const ENTITY_SIZE: f64 = 30.0;
+
+/// Sizes the editor can resize an entity to, and the range loaded levels are held to
+pub const MIN_ENTITY_SIZE: f64 = 10.0;
+pub const MAX_ENTITY_SIZE: f64 = 150.0;
+
+/// How many random positions to try before giving up on placing an entity
+const PLACEMENT_ATTEMPTS: i32 = 100;
+
+/// Where a level came from. The seed and response are enough to place the level again.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LevelSource {
+    pub prompt: String,
+    pub seed: u64,
+    pub model: String,
+    pub raw_response: String,
+
+    #[serde(default)]
+    pub template_id: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntityKind {
+    Square,
+    Circle,
+}
+
+impl EntityKind {
+    pub fn other(&self) -> EntityKind {
+        match self {
+            EntityKind::Square => EntityKind::Circle,
+            EntityKind::Circle => EntityKind::Square,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entity {
+    pub pos: VecTwo,
+
+    /// Radians
+    pub rotation: f64,
+
+    /// Width and height
+    pub size: f64,
+}
+
+impl Entity {
+    pub fn new(pos: VecTwo) -> Self {
+        Self {
+            pos,
+            rotation: 0.0,
+            size: ENTITY_SIZE,
+        }
+    }
+
+    /// Is the point inside the rendered shape of this entity
+    pub fn contains(&self, kind: EntityKind, point: VecTwo) -> bool {
+        let x = point.x - self.pos.x;
+        let y = point.y - self.pos.y;
+        let half = self.size * 0.5;
+
+        match kind {
+            EntityKind::Circle => x * x + y * y <= half * half,
+            EntityKind::Square => {
+                // rotate into the entity's local space
+                let (sin, cos) = f64::sin_cos(-self.rotation);
+                let local_x = x * cos - y * sin;
+                let local_y = x * sin + y * cos;
+                local_x.abs() <= half && local_y.abs() <= half
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub squares: Vec<Entity>,
+    pub circles: Vec<Entity>,
+
+    pub walls: Vec<Wall>,
+    pub obstacles: Vec<Obstacle>,
+
+    /// Validated behavior rules, compiled when a match starts
+    pub behaviors: Vec<BehaviorGen>,
+
+    /// Validated match rules. None is last team standing.
+    pub rules: Option<RulesGen>,
+}
+
+impl Level {
+    pub fn new() -> Self {
+        Self {
+            squares: vec![],
+            circles: vec![],
+            walls: vec![],
+            obstacles: vec![],
+            behaviors: vec![],
+            rules: None,
+        }
+    }
+
+    /// Build the level geometry from the response then place entities in the open space.
+    /// rand must return values in 0..1
+    pub fn from_response(resp: &LevelGenResponse, rand: &mut impl FnMut() -> f64) -> Self {
+        let mut level = Level::new();
+
+        for wall in &resp.walls {
+            level.walls.push(Wall::new(
+                VecTwo::new(wall.start_x, wall.start_y),
+                VecTwo::new(wall.end_x, wall.end_y),
+            ));
+        }
+
+        for obstacle in &resp.obstacles {
+            // need at least a triangle
+            if obstacle.points.len() < 3 {
+                continue;
+            }
+
+            level.obstacles.push(Obstacle::new(
+                obstacle
+                    .points
+                    .iter()
+                    .map(|p| VecTwo::new(p.x, p.y))
+                    .collect(),
+            ));
+        }
+
+        level.behaviors = resp.behaviors.clone();
+        level.rules = resp.rules.clone();
+
+        for _ in 0..resp.square_count {
+            if let Some(pos) = level.random_open_position(rand) {
+                level.squares.push(Entity::new(pos));
+            }
+        }
+
+        for _ in 0..resp.circle_count {
+            if let Some(pos) = level.random_open_position(rand) {
+                level.circles.push(Entity::new(pos));
+            }
+        }
+
+        level
+    }
+
+    pub fn entities(&self, kind: EntityKind) -> &Vec<Entity> {
+        match kind {
+            EntityKind::Square => &self.squares,
+            EntityKind::Circle => &self.circles,
+        }
+    }
+
+    pub fn entities_mut(&mut self, kind: EntityKind) -> &mut Vec<Entity> {
+        match kind {
+            EntityKind::Square => &mut self.squares,
+            EntityKind::Circle => &mut self.circles,
+        }
+    }
+
+    /// Is pos inside or touching any wall or obstacle
+    pub fn is_blocked(&self, pos: VecTwo, radius: f64) -> bool {
+        self.walls.iter().any(|w| w.blocks(pos, radius))
+            || self.obstacles.iter().any(|o| o.blocks(pos, radius))
+    }
+
+    /// Plain text summary of the placed level, for showing the level to a model
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!(
+            "The arena is a circle of radius {} centered on 0,0.",
+            GEN_RANGE
+        )];
+
+        for kind in [EntityKind::Square, EntityKind::Circle] {
+            let entities = self.entities(kind);
+            let name = team_name(kind);
+            if entities.is_empty() {
+                lines.push(format!("{}: none.", name));
+                continue;
+            }
+
+            let count = entities.len() as f64;
+            let center_x = entities.iter().map(|e| e.pos.x).sum::<f64>() / count;
+            let center_y = entities.iter().map(|e| e.pos.y).sum::<f64>() / count;
+            let spread = entities
+                .iter()
+                .map(|e| distance(e.pos, VecTwo::new(center_x, center_y)))
+                .sum::<f64>()
+                / count;
+
+            lines.push(format!(
+                "{}: {} placed, grouped around {:.0},{:.0} with an average spread of {:.0}.",
+                name,
+                entities.len(),
+                center_x,
+                center_y,
+                spread
+            ));
+        }
+
+        for wall in &self.walls {
+            lines.push(format!(
+                "Wall from {:.0},{:.0} to {:.0},{:.0}.",
+                wall.start.x, wall.start.y, wall.end.x, wall.end.y
+            ));
+        }
+
+        for obstacle in &self.obstacles {
+            let points: Vec<String> = obstacle
+                .points
+                .iter()
+                .map(|p| format!("{:.0},{:.0}", p.x, p.y))
+                .collect();
+            lines.push(format!("Obstacle with points {}.", points.join(" ")));
+        }
+
+        for behavior in &self.behaviors {
+            let rules: Vec<String> = behavior
+                .rules
+                .iter()
+                .map(|r| {
+                    format!(
+                        "when {} {} then {} {} {}",
+                        r.condition, r.condition_value, r.action, r.target, r.action_value
+                    )
+                })
+                .collect();
+            lines.push(format!(
+                "Behavior for {}: {}.",
+                behavior.team,
+                rules.join(", otherwise ")
+            ));
+        }
+
+        lines.push(format!(
+            "Match rules: {}.",
+            compile_rules(&self.rules, self).describe()
+        ));
+
+        lines.join("\n")
+    }
+
+    /// Random position within the arena that is not blocked.
+    /// None if no open position was found.
+    fn random_open_position(&self, rand: &mut impl FnMut() -> f64) -> Option<VecTwo> {
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let r = GEN_RANGE * f64::sqrt(rand());
+            let theta = rand() * 2.0 * std::f64::consts::PI;
+
+            let pos = VecTwo::new(r * f64::cos(theta), r * f64::sin(theta));
+            if !self.is_blocked(pos, ENTITY_SIZE * 0.5) {
+                return Some(pos);
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,101 @@
+use elara_engine::vectors::*;
+
+pub const WALL_THICKNESS: f64 = 10.0;
+
+/// Straight wall segment. Rendered and collided with WALL_THICKNESS.
+#[derive(Clone, Debug)]
+pub struct Wall {
+    pub start: VecTwo,
+    pub end: VecTwo,
+}
+
+impl Wall {
+    pub fn new(start: VecTwo, end: VecTwo) -> Self {
+        Self { start, end }
+    }
+
+    pub fn length(&self) -> f64 {
+        distance(self.start, self.end)
+    }
+
+    /// Does a shape of radius at pos touch this wall
+    pub fn blocks(&self, pos: VecTwo, radius: f64) -> bool {
+        distance_to_segment(pos, self.start, self.end) < (WALL_THICKNESS * 0.5) + radius
+    }
+}
+
+/// Closed polygon. Points are in order, the last point connects back to the first.
+#[derive(Clone, Debug)]
+pub struct Obstacle {
+    pub points: Vec<VecTwo>,
+}
+
+impl Obstacle {
+    pub fn new(points: Vec<VecTwo>) -> Self {
+        Self { points }
+    }
+
+    /// Returns each edge as (start, end)
+    pub fn edges(&self) -> Vec<(VecTwo, VecTwo)> {
+        let mut ret = vec![];
+        for i in 0..self.points.len() {
+            let next = (i + 1) % self.points.len();
+            ret.push((self.points[i], self.points[next]));
+        }
+        ret
+    }
+
+    /// Does a shape of radius at pos touch or sit inside this obstacle
+    pub fn blocks(&self, pos: VecTwo, radius: f64) -> bool {
+        if point_in_polygon(pos, &self.points) {
+            return true;
+        }
+
+        self.edges()
+            .iter()
+            .any(|(a, b)| distance_to_segment(pos, *a, *b) < radius)
+    }
+}
+
+pub fn distance(a: VecTwo, b: VecTwo) -> f64 {
+    let x = b.x - a.x;
+    let y = b.y - a.y;
+    f64::sqrt(x * x + y * y)
+}
+
+pub fn distance_to_segment(p: VecTwo, a: VecTwo, b: VecTwo) -> f64 {
+    let ab_x = b.x - a.x;
+    let ab_y = b.y - a.y;
+    let len_sqr = ab_x * ab_x + ab_y * ab_y;
+
+    // degenerate segment
+    if len_sqr <= 0.0 {
+        return distance(p, a);
+    }
+
+    let t = (((p.x - a.x) * ab_x + (p.y - a.y) * ab_y) / len_sqr).clamp(0.0, 1.0);
+    let closest = VecTwo::new(a.x + ab_x * t, a.y + ab_y * t);
+    distance(p, closest)
+}
+
+/// Even-odd ray cast
+pub fn point_in_polygon(p: VecTwo, points: &[VecTwo]) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[j];
+
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+
+        j = i;
+    }
+
+    inside
+}
@@ -0,0 +1,200 @@
+use crate::level::*;
+
+/// Oldest entries are dropped past this
+pub const HISTORY_MAX: usize = 100;
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub level: Level,
+    pub source: Option<LevelSource>,
+    pub code: Option<LevelCode>,
+}
+
+impl HistoryEntry {
+    /// Short summary of the entry for the history list
+    pub fn label(&self) -> String {
+        let prompt = match &self.source {
+            Some(source) => source.prompt.clone(),
+            None => String::new(),
+        };
+
+        let mut prompt: String = prompt.chars().take(24).collect();
+        if prompt.is_empty() {
+            prompt = "(no prompt)".to_string();
+        }
+
+        format!(
+            "{} | {}sq {}ci {}w {}ob",
+            prompt,
+            self.level.squares.len(),
+            self.level.circles.len(),
+            self.level.walls.len(),
+            self.level.obstacles.len()
+        )
+    }
+}
+
+/// Linear undo history of generated levels.
+/// Pushing after an undo drops the entries that could have been redone.
+#[derive(Debug)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![],
+            current: None,
+        }
+    }
+
+    pub fn entries(&self) -> &Vec<HistoryEntry> {
+        &self.entries
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if let Some(current) = self.current {
+            self.entries.truncate(current + 1);
+        }
+
+        self.entries.push(entry);
+        if self.entries.len() > HISTORY_MAX {
+            self.entries.remove(0);
+        }
+
+        self.current = Some(self.entries.len() - 1);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        matches!(self.current, Some(i) if i > 0)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        matches!(self.current, Some(i) if i + 1 < self.entries.len())
+    }
+
+    pub fn undo(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_undo() {
+            return None;
+        }
+
+        self.jump_to(self.current.unwrap() - 1)
+    }
+
+    pub fn redo(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_redo() {
+            return None;
+        }
+
+        self.jump_to(self.current.unwrap() + 1)
+    }
+
+    pub fn jump_to(&mut self, index: usize) -> Option<&HistoryEntry> {
+        if index >= self.entries.len() {
+            return None;
+        }
+
+        self.current = Some(index);
+        self.entries.get(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elara_engine::vectors::VecTwo;
+
+    /// Entry told apart by its square count
+    fn entry(squares: usize) -> HistoryEntry {
+        let mut level = Level::new();
+        for i in 0..squares {
+            level.squares.push(Entity::new(VecTwo::new(i as f64, 0.0)));
+        }
+
+        HistoryEntry {
+            level,
+            source: None,
+            code: None,
+        }
+    }
+
+    fn squares(entry: Option<&HistoryEntry>) -> Option<usize> {
+        entry.map(|e| e.level.squares.len())
+    }
+
+    #[test]
+    fn empty_history() {
+        let mut history = History::new();
+        assert!(!history.can_undo());
+        assert!(!history.can_redo());
+        assert!(history.undo().is_none());
+        assert!(history.redo().is_none());
+        assert_eq!(history.current_index(), None);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = History::new();
+        history.push(entry(1));
+        history.push(entry(2));
+        history.push(entry(3));
+
+        assert_eq!(squares(history.undo()), Some(2));
+        assert_eq!(squares(history.undo()), Some(1));
+        assert!(history.undo().is_none());
+        assert_eq!(history.current_index(), Some(0));
+
+        assert_eq!(squares(history.redo()), Some(2));
+        assert_eq!(squares(history.redo()), Some(3));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn push_after_undo_drops_redo() {
+        let mut history = History::new();
+        history.push(entry(1));
+        history.push(entry(2));
+        history.push(entry(3));
+        history.undo();
+        history.undo();
+
+        history.push(entry(4));
+        assert_eq!(history.entries().len(), 2);
+        assert!(!history.can_redo());
+        assert_eq!(squares(history.undo()), Some(1));
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let mut history = History::new();
+        for i in 0..HISTORY_MAX + 5 {
+            history.push(entry(i));
+        }
+
+        assert_eq!(history.entries().len(), HISTORY_MAX);
+        assert_eq!(history.entries()[0].level.squares.len(), 5);
+        assert_eq!(history.current_index(), Some(HISTORY_MAX - 1));
+    }
+
+    #[test]
+    fn jump_to() {
+        let mut history = History::new();
+        history.push(entry(1));
+        history.push(entry(2));
+
+        assert_eq!(squares(history.jump_to(0)), Some(1));
+        assert!(history.jump_to(2).is_none());
+        assert_eq!(history.current_index(), Some(0));
+    }
+
+    #[test]
+    fn label_without_prompt() {
+        assert_eq!(entry(2).label(), "(no prompt) | 2sq 0ci 0w 0ob");
+    }
+}
@@ -0,0 +1,432 @@
+use crate::level::*;
+use elara_engine::vectors::*;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// World units per nav grid cell
+pub const NAV_CELL_SIZE: f64 = 10.0;
+
+/// Path costs are integers so the open set can be ordered. Diagonal is roughly straight * sqrt 2.
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+/// Grid over the arena marking where a shape of the given clearance can stand
+#[derive(Clone, Debug)]
+pub struct NavGrid {
+    pub cols: i32,
+    pub rows: i32,
+    pub clearance: f64,
+
+    blocked: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn build(level: &Level, clearance: f64) -> Self {
+        let cols = ((GEN_RANGE * 2.0) / NAV_CELL_SIZE).ceil() as i32;
+        let rows = cols;
+
+        let mut grid = Self {
+            cols,
+            rows,
+            clearance,
+            blocked: vec![false; (cols * rows) as usize],
+        };
+
+        for y in 0..rows {
+            for x in 0..cols {
+                let center = grid.center(x, y);
+                let from_center = f64::sqrt(center.x * center.x + center.y * center.y);
+
+                grid.blocked[(y * cols + x) as usize] =
+                    from_center + clearance > GEN_RANGE || level.is_blocked(center, clearance);
+            }
+        }
+
+        grid
+    }
+
+    pub fn center(&self, x: i32, y: i32) -> VecTwo {
+        VecTwo::new(
+            -GEN_RANGE + (x as f64 + 0.5) * NAV_CELL_SIZE,
+            -GEN_RANGE + (y as f64 + 0.5) * NAV_CELL_SIZE,
+        )
+    }
+
+    pub fn cell_of(&self, pos: VecTwo) -> Option<(i32, i32)> {
+        let x = ((pos.x + GEN_RANGE) / NAV_CELL_SIZE).floor() as i32;
+        let y = ((pos.y + GEN_RANGE) / NAV_CELL_SIZE).floor() as i32;
+        if self.in_bounds(x, y) {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_open(&self, x: i32, y: i32) -> bool {
+        self.in_bounds(x, y) && !self.blocked[self.index(x, y)]
+    }
+
+    /// Is the straight line between two points open the whole way
+    pub fn line_clear(&self, from: VecTwo, to: VecTwo) -> bool {
+        let dist = distance(from, to);
+        let steps = (dist / (NAV_CELL_SIZE * 0.5)).ceil().max(1.0) as i32;
+
+        for i in 0..=steps {
+            let t = i as f64 / steps as f64;
+            let p = VecTwo::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t);
+            match self.cell_of(p) {
+                Some((x, y)) if self.is_open(x, y) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// A* over the grid. Returns cell centers from start to goal, ending on the goal itself.
+    /// None when the goal can't be reached.
+    pub fn find_path(&self, start: VecTwo, goal: VecTwo) -> Option<Vec<VecTwo>> {
+        let start_cell = self.nearest_open(start)?;
+        let goal_cell = self.nearest_open(goal)?;
+
+        let start_i = self.index(start_cell.0, start_cell.1);
+        let goal_i = self.index(goal_cell.0, goal_cell.1);
+
+        let mut cost: Vec<u32> = vec![u32::MAX; self.blocked.len()];
+        let mut came_from: Vec<usize> = vec![usize::MAX; self.blocked.len()];
+        let mut open: BinaryHeap<Reverse<(u32, usize)>> = BinaryHeap::new();
+
+        cost[start_i] = 0;
+        open.push(Reverse((self.heuristic(start_cell, goal_cell), start_i)));
+
+        while let Some(Reverse((_, current))) = open.pop() {
+            if current == goal_i {
+                let mut path: Vec<VecTwo> = vec![goal];
+                let mut i = came_from[current];
+                while i != usize::MAX && i != start_i {
+                    let (x, y) = self.coords(i);
+                    path.push(self.center(x, y));
+                    i = came_from[i];
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let (cx, cy) = self.coords(current);
+            for (nx, ny, step) in self.neighbors(cx, cy) {
+                let next = self.index(nx, ny);
+                let next_cost = cost[current] + step;
+
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    came_from[next] = current;
+                    open.push(Reverse((
+                        next_cost + self.heuristic((nx, ny), goal_cell),
+                        next,
+                    )));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Label each open cell with a connected region id. Blocked cells are None.
+    pub fn regions(&self) -> Vec<Option<u32>> {
+        let mut regions: Vec<Option<u32>> = vec![None; self.blocked.len()];
+        let mut next_region: u32 = 0;
+
+        for start in 0..self.blocked.len() {
+            if self.blocked[start] || regions[start].is_some() {
+                continue;
+            }
+
+            let mut stack: Vec<usize> = vec![start];
+            regions[start] = Some(next_region);
+            while let Some(current) = stack.pop() {
+                let (x, y) = self.coords(current);
+                for (nx, ny, _) in self.neighbors(x, y) {
+                    let i = self.index(nx, ny);
+                    if regions[i].is_none() {
+                        regions[i] = Some(next_region);
+                        stack.push(i);
+                    }
+                }
+            }
+
+            next_region += 1;
+        }
+
+        regions
+    }
+
+    /// Open cell at pos, or the closest open cell within a few cells
+    fn nearest_open(&self, pos: VecTwo) -> Option<(i32, i32)> {
+        let x = ((pos.x + GEN_RANGE) / NAV_CELL_SIZE).floor() as i32;
+        let y = ((pos.y + GEN_RANGE) / NAV_CELL_SIZE).floor() as i32;
+
+        for ring in 0..4 {
+            for dy in -ring..=ring {
+                for dx in -ring..=ring {
+                    if dx.abs() != ring && dy.abs() != ring {
+                        continue;
+                    }
+                    if self.is_open(x + dx, y + dy) {
+                        return Some((x + dx, y + dy));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Open neighbors with their step cost. Diagonals can't cut blocked corners.
+    fn neighbors(&self, x: i32, y: i32) -> Vec<(i32, i32, u32)> {
+        let mut ret = vec![];
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+
+                let (nx, ny) = (x + dx, y + dy);
+                if !self.is_open(nx, ny) {
+                    continue;
+                }
+
+                if dx != 0 && dy != 0 {
+                    if !self.is_open(x + dx, y) || !self.is_open(x, y + dy) {
+                        continue;
+                    }
+                    ret.push((nx, ny, DIAGONAL_COST));
+                } else {
+                    ret.push((nx, ny, STRAIGHT_COST));
+                }
+            }
+        }
+        ret
+    }
+
+    /// Octile distance
+    fn heuristic(&self, a: (i32, i32), b: (i32, i32)) -> u32 {
+        let dx = (a.0 - b.0).unsigned_abs();
+        let dy = (a.1 - b.1).unsigned_abs();
+        STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+    }
+
+    fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.cols && y < self.rows
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.cols + x) as usize
+    }
+
+    fn coords(&self, i: usize) -> (i32, i32) {
+        (i as i32 % self.cols, i as i32 / self.cols)
+    }
+}
+
+/// Warnings for entities that are stuck in geometry or walled off from every enemy
+pub fn check_reachability(level: &Level) -> Vec<String> {
+    let mut warnings: Vec<String> = vec![];
+
+    let grid = NavGrid::build(level, ENTITY_SIZE * 0.5);
+    let regions = grid.regions();
+
+    let region_of = |entity: &Entity| -> Option<u32> {
+        let (x, y) = grid.nearest_open(entity.pos)?;
+        regions[grid.index(x, y)]
+    };
+
+    for team in [EntityKind::Square, EntityKind::Circle] {
+        let enemy_regions: Vec<u32> = level
+            .entities(team.other())
+            .iter()
+            .filter_map(|e| region_of(e))
+            .collect();
+
+        let mut stuck = 0;
+        let mut enclosed = 0;
+        for entity in level.entities(team) {
+            match region_of(entity) {
+                None => stuck += 1,
+                Some(region) => {
+                    if !level.entities(team.other()).is_empty() && !enemy_regions.contains(&region)
+                    {
+                        enclosed += 1;
+                    }
+                }
+            }
+        }
+
+        let name = match team {
+            EntityKind::Square => "squares",
+            EntityKind::Circle => "circles",
+        };
+        let enemy_name = match team {
+            EntityKind::Square => "circle",
+            EntityKind::Circle => "square",
+        };
+
+        if stuck > 0 {
+            warnings.push(format!(
+                "{} {} are stuck inside level geometry",
+                stuck, name
+            ));
+        }
+        if enclosed > 0 {
+            warnings.push(format!(
+                "{} {} are walled off and can't reach any {}",
+                enclosed, name, enemy_name
+            ));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clearance() -> f64 {
+        ENTITY_SIZE * 0.5
+    }
+
+    /// Closed box of walls around the origin
+    fn boxed_level(half: f64) -> Level {
+        let corners = [
+            VecTwo::new(-half, -half),
+            VecTwo::new(half, -half),
+            VecTwo::new(half, half),
+            VecTwo::new(-half, half),
+        ];
+
+        let mut level = Level::new();
+        for i in 0..4 {
+            level
+                .walls
+                .push(Wall::new(corners[i], corners[(i + 1) % 4]));
+        }
+        level
+    }
+
+    fn path_length(start: VecTwo, path: &[VecTwo]) -> f64 {
+        let mut length = 0.0;
+        let mut prev = start;
+        for p in path {
+            length += distance(prev, *p);
+            prev = *p;
+        }
+        length
+    }
+
+    fn assert_path_open(grid: &NavGrid, path: &[VecTwo]) {
+        for p in path {
+            let (x, y) = grid.cell_of(*p).unwrap();
+            assert!(grid.is_open(x, y), "path goes through blocked cell {:?}", p);
+        }
+    }
+
+    #[test]
+    fn open_arena_path_is_straight() {
+        let grid = NavGrid::build(&Level::new(), clearance());
+        let start = VecTwo::new(-100.0, 0.0);
+        let goal = VecTwo::new(100.0, 0.0);
+
+        let path = grid.find_path(start, goal).unwrap();
+        assert_eq!(path.last().unwrap().x, goal.x);
+        assert_eq!(path.last().unwrap().y, goal.y);
+        assert!(path_length(start, &path) < 200.0 + NAV_CELL_SIZE * 2.0);
+        assert_path_open(&grid, &path);
+    }
+
+    #[test]
+    fn path_goes_around_wall() {
+        let mut level = Level::new();
+        level
+            .walls
+            .push(Wall::new(VecTwo::new(0.0, -150.0), VecTwo::new(0.0, 150.0)));
+        let grid = NavGrid::build(&level, clearance());
+        let start = VecTwo::new(-50.0, 0.0);
+        let goal = VecTwo::new(50.0, 0.0);
+
+        assert!(!grid.line_clear(start, goal));
+
+        let path = grid.find_path(start, goal).unwrap();
+        assert!(path_length(start, &path) > 300.0);
+        assert_path_open(&grid, &path);
+    }
+
+    #[test]
+    fn enclosed_goal_has_no_path() {
+        let grid = NavGrid::build(&boxed_level(60.0), clearance());
+        assert!(
+            grid.find_path(VecTwo::new(150.0, 0.0), VecTwo::new(0.0, 0.0))
+                .is_none()
+        );
+        assert!(
+            grid.find_path(VecTwo::new(10.0, 0.0), VecTwo::new(-10.0, 0.0))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn line_clear() {
+        let grid = NavGrid::build(&boxed_level(60.0), clearance());
+        assert!(grid.line_clear(VecTwo::new(100.0, -100.0), VecTwo::new(100.0, 100.0)));
+        assert!(!grid.line_clear(VecTwo::new(0.0, 0.0), VecTwo::new(100.0, 0.0)));
+    }
+
+    #[test]
+    fn arena_edge_is_blocked() {
+        let grid = NavGrid::build(&Level::new(), clearance());
+        let (x, y) = grid.cell_of(VecTwo::new(GEN_RANGE - 1.0, 0.0)).unwrap();
+        assert!(!grid.is_open(x, y));
+        assert!(grid.cell_of(VecTwo::new(GEN_RANGE * 2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn walled_off_team_is_reported() {
+        let mut level = boxed_level(60.0);
+        level.squares.push(Entity::new(VecTwo::new(0.0, 0.0)));
+        level.circles.push(Entity::new(VecTwo::new(150.0, 0.0)));
+
+        let warnings = check_reachability(&level);
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("squares are walled off"))
+        );
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("circles are walled off"))
+        );
+    }
+
+    #[test]
+    fn entity_inside_obstacle_is_stuck() {
+        let mut level = Level::new();
+        level.obstacles.push(Obstacle::new(vec![
+            VecTwo::new(-100.0, -100.0),
+            VecTwo::new(100.0, -100.0),
+            VecTwo::new(100.0, 100.0),
+            VecTwo::new(-100.0, 100.0),
+        ]));
+        level.squares.push(Entity::new(VecTwo::new(0.0, 0.0)));
+        level.circles.push(Entity::new(VecTwo::new(200.0, 0.0)));
+
+        let warnings = check_reachability(&level);
+        assert!(warnings.contains(&"1 squares are stuck inside level geometry".to_string()));
+    }
+
+    #[test]
+    fn open_level_has_no_warnings() {
+        let mut level = Level::new();
+        level.squares.push(Entity::new(VecTwo::new(-100.0, 0.0)));
+        level.circles.push(Entity::new(VecTwo::new(100.0, 0.0)));
+        assert!(check_reachability(&level).is_empty());
+    }
+}
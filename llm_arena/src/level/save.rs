@@ -0,0 +1,200 @@
+use crate::{
+    ai_level_gen::{BehaviorGen, RulesGen, ValidationLimits, ValidationReport, validate_level},
+    level::*,
+};
+use elara_engine::vectors::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Bump when the file layout changes. Older versions must still load.
+/// 2 added entity rotation and size
+/// 3 added behaviors
+/// 4 added match rules
+pub const LEVEL_FILE_VERSION: u32 = 4;
+
+#[derive(Debug)]
+pub enum SaveError {
+    /// Error reading or writing the file
+    Io(std::io::Error),
+
+    /// Serde error converting the level to or from json
+    Serde(serde_json::Error),
+
+    /// File was written by a version we don't know how to read
+    UnsupportedVersion { version: u32 },
+
+    /// Level broke the validation limits and the policy is Reject
+    Rejected { issues: Vec<String> },
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(err: serde_json::Error) -> Self {
+        SaveError::Serde(err)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PointData {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl From<VecTwo> for PointData {
+    fn from(v: VecTwo) -> Self {
+        Self { x: v.x, y: v.y }
+    }
+}
+
+impl From<&PointData> for VecTwo {
+    fn from(p: &PointData) -> Self {
+        VecTwo::new(p.x, p.y)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntityData {
+    pub x: f64,
+    pub y: f64,
+
+    #[serde(default)]
+    pub rotation: f64,
+
+    #[serde(default = "default_entity_size")]
+    pub size: f64,
+}
+
+fn default_entity_size() -> f64 {
+    ENTITY_SIZE
+}
+
+impl From<&Entity> for EntityData {
+    fn from(e: &Entity) -> Self {
+        Self {
+            x: e.pos.x,
+            y: e.pos.y,
+            rotation: e.rotation,
+            size: e.size,
+        }
+    }
+}
+
+impl From<&EntityData> for Entity {
+    fn from(e: &EntityData) -> Self {
+        Entity {
+            pos: VecTwo::new(e.x, e.y),
+            rotation: e.rotation,
+            size: e.size,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WallData {
+    pub start: PointData,
+    pub end: PointData,
+}
+
+/// On disk layout of a level
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LevelFile {
+    pub version: u32,
+    pub source: LevelSource,
+
+    pub squares: Vec<EntityData>,
+    pub circles: Vec<EntityData>,
+    pub walls: Vec<WallData>,
+    pub obstacles: Vec<Vec<PointData>>,
+
+    #[serde(default)]
+    pub behaviors: Vec<BehaviorGen>,
+
+    #[serde(default)]
+    pub rules: Option<RulesGen>,
+}
+
+impl LevelFile {
+    pub fn new(source: &LevelSource, level: &Level) -> Self {
+        Self {
+            version: LEVEL_FILE_VERSION,
+            source: source.clone(),
+
+            squares: level.squares.iter().map(|e| e.into()).collect(),
+            circles: level.circles.iter().map(|e| e.into()).collect(),
+            walls: level
+                .walls
+                .iter()
+                .map(|w| WallData {
+                    start: w.start.into(),
+                    end: w.end.into(),
+                })
+                .collect(),
+            obstacles: level
+                .obstacles
+                .iter()
+                .map(|o| o.points.iter().map(|p| (*p).into()).collect())
+                .collect(),
+            behaviors: level.behaviors.clone(),
+            rules: level.rules.clone(),
+        }
+    }
+
+    pub fn to_level(&self) -> Level {
+        let mut level = Level::new();
+
+        level.squares = self.squares.iter().map(|e| e.into()).collect();
+        level.circles = self.circles.iter().map(|e| e.into()).collect();
+        level.walls = self
+            .walls
+            .iter()
+            .map(|w| Wall::new((&w.start).into(), (&w.end).into()))
+            .collect();
+        level.obstacles = self
+            .obstacles
+            .iter()
+            .map(|o| Obstacle::new(o.iter().map(|p| p.into()).collect()))
+            .collect();
+        level.behaviors = self.behaviors.clone();
+        level.rules = self.rules.clone();
+
+        level
+    }
+}
+
+pub fn save_level(path: &Path, source: &LevelSource, level: &Level) -> Result<(), SaveError> {
+    let file = LevelFile::new(source, level);
+    let json = serde_json::to_string_pretty(&file)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// Loaded levels go through the same limits as generated ones, files can be edited by hand.
+/// With the Clamp policy the report lists what was fixed.
+pub fn load_level(
+    path: &Path,
+    limits: &ValidationLimits,
+) -> Result<(LevelSource, Level, ValidationReport), SaveError> {
+    let json = std::fs::read_to_string(path)?;
+    let file: LevelFile = serde_json::from_str(&json)?;
+
+    if file.version > LEVEL_FILE_VERSION {
+        return Err(SaveError::UnsupportedVersion {
+            version: file.version,
+        });
+    }
+
+    let mut level = file.to_level();
+    let report = validate_level(&mut level, limits);
+    if report.rejected {
+        return Err(SaveError::Rejected {
+            issues: report.issues,
+        });
+    }
+
+    Ok((file.source.clone(), level, report))
+}
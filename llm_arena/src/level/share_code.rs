@@ -0,0 +1,422 @@
+use crate::ai_level_gen::*;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{Deserialize, Serialize};
+
+/// Every level code starts with this. The number is the code format version.
+pub const LEVEL_CODE_PREFIX: &str = "LA2-";
+
+/// Codes from before the extras section. Still decoded.
+pub const LEVEL_CODE_PREFIX_V1: &str = "LA1-";
+
+/// Model name recorded for levels rebuilt from a code
+pub const LEVEL_CODE_MODEL: &str = "level_code";
+
+#[derive(Debug)]
+pub enum LevelCodeError {
+    /// Text does not start with a known prefix
+    MissingPrefix,
+
+    /// Body is not valid base64
+    Base64,
+
+    /// Extras section is not valid json
+    Extras,
+
+    /// Ran out of bytes before the level was fully read
+    Truncated,
+
+    /// Extra bytes after the level
+    TrailingBytes,
+}
+
+/// Sections of the response that are rare or still changing. Stored as json after the
+/// binary sections so new fields don't need a new code version.
+#[derive(Default, Serialize, Deserialize)]
+struct CodeExtras {
+    #[serde(default)]
+    behaviors: Vec<BehaviorGen>,
+
+    #[serde(default)]
+    rules: Option<RulesGen>,
+}
+
+/// Everything needed to place a level again without calling the model
+#[derive(Clone, Debug)]
+pub struct LevelCode {
+    pub prompt_hash: u32,
+    pub seed: u64,
+    pub response: LevelGenResponse,
+}
+
+impl LevelCode {
+    pub fn new(prompt: &str, seed: u64, response: &LevelGenResponse) -> Self {
+        Self {
+            prompt_hash: prompt_hash(prompt),
+            seed,
+            response: response.clone(),
+        }
+    }
+
+    pub fn is_level_code(text: &str) -> bool {
+        let text = text.trim();
+        text.starts_with(LEVEL_CODE_PREFIX) || text.starts_with(LEVEL_CODE_PREFIX_V1)
+    }
+
+    pub fn encode(&self) -> String {
+        let mut bytes: Vec<u8> = vec![];
+
+        bytes.extend_from_slice(&self.prompt_hash.to_le_bytes());
+        write_varint(&mut bytes, self.seed);
+        bytes.push(self.response.valid as u8);
+        write_varint(&mut bytes, zigzag(self.response.square_count));
+        write_varint(&mut bytes, zigzag(self.response.circle_count));
+
+        write_varint(&mut bytes, self.response.walls.len() as u64);
+        for wall in &self.response.walls {
+            write_f64(&mut bytes, wall.start_x);
+            write_f64(&mut bytes, wall.start_y);
+            write_f64(&mut bytes, wall.end_x);
+            write_f64(&mut bytes, wall.end_y);
+        }
+
+        write_varint(&mut bytes, self.response.obstacles.len() as u64);
+        for obstacle in &self.response.obstacles {
+            write_varint(&mut bytes, obstacle.points.len() as u64);
+            for p in &obstacle.points {
+                write_f64(&mut bytes, p.x);
+                write_f64(&mut bytes, p.y);
+            }
+        }
+
+        let extras = CodeExtras {
+            behaviors: self.response.behaviors.clone(),
+            rules: self.response.rules.clone(),
+        };
+        let extras_json = serde_json::to_string(&extras).unwrap();
+        write_varint(&mut bytes, extras_json.len() as u64);
+        bytes.extend_from_slice(extras_json.as_bytes());
+
+        format!("{}{}", LEVEL_CODE_PREFIX, URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn decode(text: &str) -> Result<Self, LevelCodeError> {
+        let text = text.trim();
+        let (body, has_extras) = if let Some(body) = text.strip_prefix(LEVEL_CODE_PREFIX) {
+            (body, true)
+        } else if let Some(body) = text.strip_prefix(LEVEL_CODE_PREFIX_V1) {
+            (body, false)
+        } else {
+            return Err(LevelCodeError::MissingPrefix);
+        };
+        let bytes = URL_SAFE_NO_PAD
+            .decode(body)
+            .map_err(|_| LevelCodeError::Base64)?;
+
+        let mut reader = Reader {
+            bytes: &bytes,
+            i: 0,
+        };
+
+        let prompt_hash = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+        let seed = reader.varint()?;
+        let valid = reader.take(1)?[0] != 0;
+        let square_count = unzigzag(reader.varint()?);
+        let circle_count = unzigzag(reader.varint()?);
+
+        let mut walls: Vec<WallGen> = vec![];
+        for _ in 0..reader.count()? {
+            walls.push(WallGen {
+                start_x: reader.f64()?,
+                start_y: reader.f64()?,
+                end_x: reader.f64()?,
+                end_y: reader.f64()?,
+            });
+        }
+
+        let mut obstacles: Vec<ObstacleGen> = vec![];
+        for _ in 0..reader.count()? {
+            let mut points: Vec<PointGen> = vec![];
+            for _ in 0..reader.count()? {
+                points.push(PointGen {
+                    x: reader.f64()?,
+                    y: reader.f64()?,
+                });
+            }
+            obstacles.push(ObstacleGen { points });
+        }
+
+        let mut extras = CodeExtras::default();
+        if has_extras {
+            let len = reader.count()?;
+            extras =
+                serde_json::from_slice(reader.take(len)?).map_err(|_| LevelCodeError::Extras)?;
+        }
+
+        if reader.i != bytes.len() {
+            return Err(LevelCodeError::TrailingBytes);
+        }
+
+        Ok(Self {
+            prompt_hash,
+            seed,
+            response: LevelGenResponse {
+                valid,
+                error: String::new(),
+                square_count,
+                circle_count,
+                walls,
+                obstacles,
+                behaviors: extras.behaviors,
+                rules: extras.rules,
+            },
+        })
+    }
+}
+
+/// FNV-1a. Stable across runs and platforms, unlike the std hasher.
+pub fn prompt_hash(prompt: &str) -> u32 {
+    let mut hash: u32 = 0x811C9DC5;
+    for b in prompt.trim().as_bytes() {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+fn zigzag(v: i32) -> u64 {
+    ((v << 1) ^ (v >> 31)) as u32 as u64
+}
+
+fn unzigzag(v: u64) -> i32 {
+    let v = v as u32;
+    ((v >> 1) as i32) ^ -((v & 1) as i32)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let b = (v & 0x7F) as u8;
+        v >>= 7;
+
+        if v == 0 {
+            bytes.push(b);
+            return;
+        }
+        bytes.push(b | 0x80);
+    }
+}
+
+fn write_f64(bytes: &mut Vec<u8>, v: f64) {
+    bytes.extend_from_slice(&v.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LevelCodeError> {
+        if self.i + len > self.bytes.len() {
+            return Err(LevelCodeError::Truncated);
+        }
+
+        let ret = &self.bytes[self.i..self.i + len];
+        self.i += len;
+        Ok(ret)
+    }
+
+    fn varint(&mut self) -> Result<u64, LevelCodeError> {
+        let mut v: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let b = self.take(1)?[0];
+            v |= ((b & 0x7F) as u64) << shift;
+
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+
+            shift += 7;
+            if shift >= 64 {
+                return Err(LevelCodeError::Truncated);
+            }
+        }
+    }
+
+    /// Length prefix. Can't be more than the remaining bytes, so a bad code can't allocate forever.
+    fn count(&mut self) -> Result<usize, LevelCodeError> {
+        let count = self.varint()? as usize;
+        if count > self.bytes.len() - self.i {
+            return Err(LevelCodeError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn f64(&mut self) -> Result<f64, LevelCodeError> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_response() -> LevelGenResponse {
+        LevelGenResponse {
+            valid: true,
+            error: String::new(),
+            square_count: 12,
+            circle_count: -3,
+            walls: vec![WallGen {
+                start_x: -100.5,
+                start_y: 20.0,
+                end_x: 100.25,
+                end_y: 20.0,
+            }],
+            obstacles: vec![ObstacleGen {
+                points: vec![
+                    PointGen { x: 0.0, y: 0.0 },
+                    PointGen { x: 50.0, y: 0.1 },
+                    PointGen { x: 25.0, y: 40.0 },
+                ],
+            }],
+            behaviors: vec![BehaviorGen {
+                team: "squares".to_string(),
+                rules: vec![BehaviorRuleGen {
+                    condition: "health_below".to_string(),
+                    condition_value: 0.3,
+                    action: "flee".to_string(),
+                    target: "nearest_enemy".to_string(),
+                    action_value: 0.0,
+                }],
+            }],
+            rules: Some(RulesGen {
+                objective: "kills".to_string(),
+                team: String::new(),
+                kill_target: 5,
+                time_limit: 90.0,
+            }),
+        }
+    }
+
+    fn code() -> LevelCode {
+        LevelCode::new("three squares", 987_654_321_012, &full_response())
+    }
+
+    /// Body bytes of an encoded code
+    fn body(code: &str) -> Vec<u8> {
+        URL_SAFE_NO_PAD
+            .decode(code.strip_prefix(LEVEL_CODE_PREFIX).unwrap())
+            .unwrap()
+    }
+
+    fn with_body(bytes: &[u8]) -> String {
+        format!("{}{}", LEVEL_CODE_PREFIX, URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    #[test]
+    fn round_trip() {
+        let code = code();
+        let decoded = LevelCode::decode(&code.encode()).unwrap();
+
+        assert_eq!(decoded.prompt_hash, code.prompt_hash);
+        assert_eq!(decoded.seed, code.seed);
+        assert_eq!(
+            serde_json::to_string(&decoded.response).unwrap(),
+            serde_json::to_string(&code.response).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trip_empty_response() {
+        let code = LevelCode::new("", 0, &LevelGenResponse::default());
+        let decoded = LevelCode::decode(&code.encode()).unwrap();
+
+        assert!(!decoded.response.valid);
+        assert_eq!(decoded.response.square_count, 0);
+        assert!(decoded.response.walls.is_empty());
+        assert!(decoded.response.rules.is_none());
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_ignored() {
+        let text = format!("  {}\n", code().encode());
+        assert!(LevelCode::is_level_code(&text));
+        assert!(LevelCode::decode(&text).is_ok());
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = body(&code().encode());
+        for len in [0, 3, 10, bytes.len() - 1] {
+            assert!(
+                matches!(
+                    LevelCode::decode(&with_body(&bytes[..len])),
+                    Err(LevelCodeError::Truncated) | Err(LevelCodeError::Extras)
+                ),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut bytes = body(&code().encode());
+        bytes.push(0);
+        assert!(matches!(
+            LevelCode::decode(&with_body(&bytes)),
+            Err(LevelCodeError::TrailingBytes)
+        ));
+    }
+
+    #[test]
+    fn corrupt_base64() {
+        let text = format!("{}not*base64!", LEVEL_CODE_PREFIX);
+        assert!(matches!(
+            LevelCode::decode(&text),
+            Err(LevelCodeError::Base64)
+        ));
+    }
+
+    #[test]
+    fn corrupt_extras() {
+        let mut bytes = body(&code().encode());
+        let last = bytes.len() - 1;
+        bytes[last] = b'{';
+        assert!(matches!(
+            LevelCode::decode(&with_body(&bytes)),
+            Err(LevelCodeError::Extras)
+        ));
+    }
+
+    #[test]
+    fn unknown_version() {
+        let text = code().encode().replacen(LEVEL_CODE_PREFIX, "LA9-", 1);
+        assert!(!LevelCode::is_level_code(&text));
+        assert!(matches!(
+            LevelCode::decode(&text),
+            Err(LevelCodeError::MissingPrefix)
+        ));
+    }
+
+    #[test]
+    fn huge_length_prefix_is_rejected() {
+        // prompt hash, seed, valid, counts, then a wall count far past the end of the code
+        let mut bytes = vec![0, 0, 0, 0, 0, 1, 0, 0];
+        write_varint(&mut bytes, u64::MAX >> 1);
+        assert!(matches!(
+            LevelCode::decode(&with_body(&bytes)),
+            Err(LevelCodeError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn prompt_hash_ignores_surrounding_whitespace() {
+        assert_eq!(
+            prompt_hash(" three squares\n"),
+            prompt_hash("three squares")
+        );
+        assert_ne!(prompt_hash("three squares"), prompt_hash("three circles"));
+    }
+}
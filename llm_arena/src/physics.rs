@@ -0,0 +1,622 @@
+use crate::level::*;
+use elara_engine::vectors::*;
+
+/// Contact solver passes per step. More passes settle crowds better.
+const SOLVER_ITERATIONS: i32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Collider {
+    Circle {
+        radius: f64,
+    },
+
+    /// Square box rotated around its center. Rotation in radians.
+    Box {
+        half: f64,
+        rotation: f64,
+    },
+}
+
+impl Collider {
+    /// Collider matching how the entity is rendered
+    pub fn for_entity(kind: EntityKind, size: f64, rotation: f64) -> Self {
+        match kind {
+            EntityKind::Circle => Collider::Circle { radius: size * 0.5 },
+            EntityKind::Square => Collider::Box {
+                half: size * 0.5,
+                rotation,
+            },
+        }
+    }
+
+    /// Radius of a circle around the center that contains the whole shape
+    pub fn bounding_radius(&self) -> f64 {
+        match self {
+            Collider::Circle { radius } => *radius,
+            Collider::Box { half, .. } => half * std::f64::consts::SQRT_2,
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        match self {
+            Collider::Circle { radius } => std::f64::consts::PI * radius * radius,
+            Collider::Box { half, .. } => 4.0 * half * half,
+        }
+    }
+}
+
+/// Overlap between two shapes. Normal is unit length and points from the first shape to the second.
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub normal: VecTwo,
+    pub depth: f64,
+}
+
+impl Contact {
+    fn flipped(self) -> Self {
+        Self {
+            normal: scale(self.normal, -1.0),
+            depth: self.depth,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Body {
+    pub pos: VecTwo,
+    pub vel: VecTwo,
+    pub collider: Collider,
+
+    /// Zero for immovable bodies
+    pub inv_mass: f64,
+
+    /// 0 is no bounce, 1 is perfectly elastic
+    pub restitution: f64,
+}
+
+impl Body {
+    /// Mass is taken from the collider area
+    pub fn new(pos: VecTwo, vel: VecTwo, collider: Collider, restitution: f64) -> Self {
+        let area = collider.area();
+        Self {
+            pos,
+            vel,
+            collider,
+            inv_mass: if area > 0.0 { 1.0 / area } else { 0.0 },
+            restitution,
+        }
+    }
+}
+
+pub fn collide(a_pos: VecTwo, a: &Collider, b_pos: VecTwo, b: &Collider) -> Option<Contact> {
+    // broad phase
+    let reach = a.bounding_radius() + b.bounding_radius();
+    let d = sub(b_pos, a_pos);
+    if dot(d, d) > reach * reach {
+        return None;
+    }
+
+    match (a, b) {
+        (Collider::Circle { radius: ra }, Collider::Circle { radius: rb }) => {
+            let dist = length(d);
+            let depth = ra + rb - dist;
+            if depth <= 0.0 {
+                return None;
+            }
+
+            let normal = if dist > 0.0 {
+                scale(d, 1.0 / dist)
+            } else {
+                VecTwo::new(1.0, 0.0)
+            };
+            Some(Contact { normal, depth })
+        }
+
+        (Collider::Box { half, rotation }, Collider::Circle { radius }) => {
+            box_circle(a_pos, *half, *rotation, b_pos, *radius)
+        }
+
+        (Collider::Circle { radius }, Collider::Box { half, rotation }) => {
+            box_circle(b_pos, *half, *rotation, a_pos, *radius).map(|c| c.flipped())
+        }
+
+        (
+            Collider::Box {
+                half: ha,
+                rotation: rot_a,
+            },
+            Collider::Box {
+                half: hb,
+                rotation: rot_b,
+            },
+        ) => box_box(a_pos, *ha, *rot_a, b_pos, *hb, *rot_b),
+    }
+}
+
+pub fn overlaps(a_pos: VecTwo, a: &Collider, b_pos: VecTwo, b: &Collider) -> bool {
+    collide(a_pos, a, b_pos, b).is_some()
+}
+
+/// Contact between a thick line segment and a shape. Normal points from the segment to the shape.
+pub fn collide_segment(
+    pos: VecTwo,
+    collider: &Collider,
+    start: VecTwo,
+    end: VecTwo,
+    thickness: f64,
+) -> Option<Contact> {
+    let closest = closest_on_segment(pos, start, end);
+    collide(
+        closest,
+        &Collider::Circle {
+            radius: thickness * 0.5,
+        },
+        pos,
+        collider,
+    )
+}
+
+/// Deepest contact between a shape and the level walls, obstacles and arena edge.
+/// Normal points from the geometry to the shape.
+pub fn collide_static(
+    pos: VecTwo,
+    collider: &Collider,
+    walls: &[Wall],
+    obstacles: &[Obstacle],
+) -> Option<Contact> {
+    let mut deepest: Option<Contact> = arena_contact(pos, collider);
+
+    let mut consider = |contact: Option<Contact>| {
+        if let Some(contact) = contact {
+            if deepest.map(|d| contact.depth > d.depth).unwrap_or(true) {
+                deepest = Some(contact);
+            }
+        }
+    };
+
+    for wall in walls {
+        consider(collide_segment(
+            pos,
+            collider,
+            wall.start,
+            wall.end,
+            WALL_THICKNESS,
+        ));
+    }
+
+    for obstacle in obstacles {
+        if point_in_polygon(pos, &obstacle.points) {
+            // inside, push out through the nearest edge
+            let mut best: Option<(VecTwo, f64)> = None;
+            for (a, b) in obstacle.edges() {
+                let closest = closest_on_segment(pos, a, b);
+                let dist = distance(pos, closest);
+                if best.map(|(_, d)| dist < d).unwrap_or(true) {
+                    best = Some((closest, dist));
+                }
+            }
+
+            if let Some((closest, dist)) = best {
+                let normal = if dist > 0.0 {
+                    scale(sub(closest, pos), 1.0 / dist)
+                } else {
+                    VecTwo::new(1.0, 0.0)
+                };
+                consider(Some(Contact {
+                    normal,
+                    depth: dist + collider.bounding_radius(),
+                }));
+            }
+            continue;
+        }
+
+        for (a, b) in obstacle.edges() {
+            consider(collide_segment(pos, collider, a, b, WALL_THICKNESS));
+        }
+    }
+
+    deepest
+}
+
+/// Keep shapes inside the circular arena. Normal points inward.
+pub fn arena_contact(pos: VecTwo, collider: &Collider) -> Option<Contact> {
+    let center_dist = length(pos);
+
+    // furthest point of the shape from the arena center
+    let reach = match collider {
+        Collider::Circle { radius } => center_dist + radius,
+        Collider::Box { half, rotation } => box_corners(pos, *half, *rotation)
+            .iter()
+            .map(|c| length(*c))
+            .fold(0.0, f64::max),
+    };
+
+    let depth = reach - GEN_RANGE;
+    if depth <= 0.0 {
+        return None;
+    }
+
+    let normal = if center_dist > 0.0 {
+        scale(pos, -1.0 / center_dist)
+    } else {
+        VecTwo::new(1.0, 0.0)
+    };
+    Some(Contact { normal, depth })
+}
+
+/// Move bodies by their velocity then push apart anything overlapping, bouncing by restitution
+pub fn step_bodies(bodies: &mut [Body], walls: &[Wall], obstacles: &[Obstacle], dt: f64) {
+    for body in bodies.iter_mut() {
+        body.pos = add(body.pos, scale(body.vel, dt));
+    }
+
+    for _ in 0..SOLVER_ITERATIONS {
+        for i in 0..bodies.len() {
+            for j in (i + 1)..bodies.len() {
+                let (left, right) = bodies.split_at_mut(j);
+                resolve_pair(&mut left[i], &mut right[0]);
+            }
+        }
+
+        for body in bodies.iter_mut() {
+            if let Some(contact) = collide_static(body.pos, &body.collider, walls, obstacles) {
+                resolve_static(body, contact);
+            }
+        }
+    }
+}
+
+fn resolve_pair(a: &mut Body, b: &mut Body) {
+    let Some(contact) = collide(a.pos, &a.collider, b.pos, &b.collider) else {
+        return;
+    };
+
+    let total_inv_mass = a.inv_mass + b.inv_mass;
+    if total_inv_mass <= 0.0 {
+        return;
+    }
+
+    let n = contact.normal;
+
+    // bounce, only when closing
+    let closing = dot(sub(b.vel, a.vel), n);
+    if closing < 0.0 {
+        let restitution = f64::min(a.restitution, b.restitution);
+        let impulse = -(1.0 + restitution) * closing / total_inv_mass;
+        a.vel = sub(a.vel, scale(n, impulse * a.inv_mass));
+        b.vel = add(b.vel, scale(n, impulse * b.inv_mass));
+    }
+
+    // separate, lighter bodies move more
+    a.pos = sub(a.pos, scale(n, contact.depth * a.inv_mass / total_inv_mass));
+    b.pos = add(b.pos, scale(n, contact.depth * b.inv_mass / total_inv_mass));
+}
+
+fn resolve_static(body: &mut Body, contact: Contact) {
+    let n = contact.normal;
+
+    let closing = dot(body.vel, n);
+    if closing < 0.0 {
+        body.vel = sub(body.vel, scale(n, (1.0 + body.restitution) * closing));
+    }
+
+    body.pos = add(body.pos, scale(n, contact.depth));
+}
+
+fn box_circle(
+    box_pos: VecTwo,
+    half: f64,
+    rotation: f64,
+    circle_pos: VecTwo,
+    radius: f64,
+) -> Option<Contact> {
+    let local = rotate(sub(circle_pos, box_pos), -rotation);
+    let clamped = VecTwo::new(local.x.clamp(-half, half), local.y.clamp(-half, half));
+
+    let inside = local.x.abs() <= half && local.y.abs() <= half;
+    if inside {
+        // push out along the shallowest axis
+        let pen_x = half - local.x.abs();
+        let pen_y = half - local.y.abs();
+
+        let (normal, depth) = if pen_x < pen_y {
+            (VecTwo::new(sign(local.x), 0.0), pen_x + radius)
+        } else {
+            (VecTwo::new(0.0, sign(local.y)), pen_y + radius)
+        };
+
+        return Some(Contact {
+            normal: rotate(normal, rotation),
+            depth,
+        });
+    }
+
+    let diff = sub(local, clamped);
+    let dist = length(diff);
+    if dist >= radius {
+        return None;
+    }
+
+    Some(Contact {
+        normal: rotate(scale(diff, 1.0 / dist), rotation),
+        depth: radius - dist,
+    })
+}
+
+/// Separating axis test between two rotated squares
+fn box_box(
+    a_pos: VecTwo,
+    a_half: f64,
+    a_rot: f64,
+    b_pos: VecTwo,
+    b_half: f64,
+    b_rot: f64,
+) -> Option<Contact> {
+    let a_axes = box_axes(a_rot);
+    let b_axes = box_axes(b_rot);
+    let d = sub(b_pos, a_pos);
+
+    let mut best: Option<Contact> = None;
+    for axis in a_axes.iter().chain(b_axes.iter()) {
+        let a_extent = a_half * (dot(a_axes[0], *axis).abs() + dot(a_axes[1], *axis).abs());
+        let b_extent = b_half * (dot(b_axes[0], *axis).abs() + dot(b_axes[1], *axis).abs());
+        let center = dot(d, *axis);
+
+        let depth = a_extent + b_extent - center.abs();
+        if depth <= 0.0 {
+            return None;
+        }
+
+        if best.map(|b| depth < b.depth).unwrap_or(true) {
+            best = Some(Contact {
+                normal: scale(*axis, sign(center)),
+                depth,
+            });
+        }
+    }
+
+    best
+}
+
+fn box_axes(rotation: f64) -> [VecTwo; 2] {
+    let (sin, cos) = f64::sin_cos(rotation);
+    [VecTwo::new(cos, sin), VecTwo::new(-sin, cos)]
+}
+
+pub fn box_corners(pos: VecTwo, half: f64, rotation: f64) -> [VecTwo; 4] {
+    [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+        .map(|(x, y)| add(pos, rotate(VecTwo::new(x * half, y * half), rotation)))
+}
+
+fn closest_on_segment(p: VecTwo, a: VecTwo, b: VecTwo) -> VecTwo {
+    let ab = sub(b, a);
+    let len_sqr = dot(ab, ab);
+    if len_sqr <= 0.0 {
+        return a;
+    }
+
+    let t = (dot(sub(p, a), ab) / len_sqr).clamp(0.0, 1.0);
+    add(a, scale(ab, t))
+}
+
+fn sign(v: f64) -> f64 {
+    if v < 0.0 { -1.0 } else { 1.0 }
+}
+
+fn add(a: VecTwo, b: VecTwo) -> VecTwo {
+    VecTwo::new(a.x + b.x, a.y + b.y)
+}
+
+fn sub(a: VecTwo, b: VecTwo) -> VecTwo {
+    VecTwo::new(a.x - b.x, a.y - b.y)
+}
+
+fn scale(v: VecTwo, s: f64) -> VecTwo {
+    VecTwo::new(v.x * s, v.y * s)
+}
+
+fn dot(a: VecTwo, b: VecTwo) -> f64 {
+    a.x * b.x + a.y * b.y
+}
+
+fn length(v: VecTwo) -> f64 {
+    f64::sqrt(dot(v, v))
+}
+
+fn rotate(v: VecTwo, angle: f64) -> VecTwo {
+    let (sin, cos) = f64::sin_cos(angle);
+    VecTwo::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn circle(radius: f64) -> Collider {
+        Collider::Circle { radius }
+    }
+
+    fn square(half: f64, rotation: f64) -> Collider {
+        Collider::Box { half, rotation }
+    }
+
+    fn origin() -> VecTwo {
+        VecTwo::new(0.0, 0.0)
+    }
+
+    fn assert_normal(contact: &Contact, x: f64, y: f64) {
+        assert!(
+            (contact.normal.x - x).abs() < 1e-6 && (contact.normal.y - y).abs() < 1e-6,
+            "normal {:?} expected {} {}",
+            contact.normal,
+            x,
+            y
+        );
+    }
+
+    #[test]
+    fn circles() {
+        let contact = collide(origin(), &circle(5.0), VecTwo::new(8.0, 0.0), &circle(5.0)).unwrap();
+        assert!((contact.depth - 2.0).abs() < EPSILON);
+        assert_normal(&contact, 1.0, 0.0);
+
+        assert!(collide(origin(), &circle(5.0), VecTwo::new(10.0, 0.0), &circle(5.0)).is_none());
+    }
+
+    #[test]
+    fn box_and_circle() {
+        let contact = collide(
+            origin(),
+            &square(10.0, 0.0),
+            VecTwo::new(13.0, 0.0),
+            &circle(5.0),
+        )
+        .unwrap();
+        assert!((contact.depth - 2.0).abs() < EPSILON);
+        assert_normal(&contact, 1.0, 0.0);
+
+        // circle first flips the normal
+        let flipped = collide(
+            VecTwo::new(13.0, 0.0),
+            &circle(5.0),
+            origin(),
+            &square(10.0, 0.0),
+        )
+        .unwrap();
+        assert_normal(&flipped, -1.0, 0.0);
+
+        // inside the bounding circle but clear of the box
+        assert!(
+            collide(
+                origin(),
+                &square(10.0, 0.0),
+                VecTwo::new(14.0, 14.0),
+                &circle(5.0)
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn circle_inside_box() {
+        let contact = collide(
+            origin(),
+            &square(10.0, 0.0),
+            VecTwo::new(0.0, 8.0),
+            &circle(1.0),
+        )
+        .unwrap();
+        assert!((contact.depth - 3.0).abs() < EPSILON);
+        assert_normal(&contact, 0.0, 1.0);
+    }
+
+    #[test]
+    fn boxes_aligned() {
+        let contact = collide(
+            origin(),
+            &square(10.0, 0.0),
+            VecTwo::new(0.0, -15.0),
+            &square(10.0, 0.0),
+        )
+        .unwrap();
+        assert!((contact.depth - 5.0).abs() < EPSILON);
+        assert_normal(&contact, 0.0, -1.0);
+    }
+
+    #[test]
+    fn boxes_rotated() {
+        let rotation = std::f64::consts::FRAC_PI_4;
+        let reach = 10.0 + 10.0 * std::f64::consts::SQRT_2;
+
+        let contact = collide(
+            origin(),
+            &square(10.0, 0.0),
+            VecTwo::new(22.0, 0.0),
+            &square(10.0, rotation),
+        )
+        .unwrap();
+        assert!((contact.depth - (reach - 22.0)).abs() < 1e-6);
+        assert_normal(&contact, 1.0, 0.0);
+
+        assert!(
+            collide(
+                origin(),
+                &square(10.0, 0.0),
+                VecTwo::new(25.0, 0.0),
+                &square(10.0, rotation),
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn boxes_separated_on_a_rotated_axis() {
+        // bounding circles overlap, the boxes' own diagonal axis separates them
+        let rotation = std::f64::consts::FRAC_PI_4;
+        assert!(
+            collide(
+                origin(),
+                &square(10.0, rotation),
+                VecTwo::new(19.0, 19.0),
+                &square(10.0, rotation),
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn arena_edge() {
+        let contact = arena_contact(VecTwo::new(GEN_RANGE - 2.0, 0.0), &circle(5.0)).unwrap();
+        assert!((contact.depth - 3.0).abs() < EPSILON);
+        assert_normal(&contact, -1.0, 0.0);
+
+        assert!(arena_contact(origin(), &circle(5.0)).is_none());
+    }
+
+    #[test]
+    fn wall_pushes_away() {
+        let walls = [Wall::new(VecTwo::new(-50.0, 0.0), VecTwo::new(50.0, 0.0))];
+        let contact = collide_static(VecTwo::new(0.0, 6.0), &circle(5.0), &walls, &[]).unwrap();
+        assert!((contact.depth - (WALL_THICKNESS * 0.5 + 5.0 - 6.0)).abs() < EPSILON);
+        assert_normal(&contact, 0.0, 1.0);
+    }
+
+    #[test]
+    fn step_separates_overlapping_bodies() {
+        let mut bodies = [
+            Body::new(VecTwo::new(-2.0, 0.0), origin(), circle(5.0), 0.0),
+            Body::new(VecTwo::new(2.0, 0.0), origin(), circle(5.0), 0.0),
+        ];
+        step_bodies(&mut bodies, &[], &[], 1.0 / 60.0);
+
+        let gap = bodies[1].pos.x - bodies[0].pos.x;
+        assert!(gap >= 10.0 - 1e-6, "gap {}", gap);
+
+        // equal mass, so both moved the same amount
+        assert!((bodies[0].pos.x + bodies[1].pos.x).abs() < EPSILON);
+    }
+
+    #[test]
+    fn step_bounces_closing_bodies() {
+        let mut bodies = [
+            Body::new(
+                VecTwo::new(-5.0, 0.0),
+                VecTwo::new(60.0, 0.0),
+                circle(5.0),
+                1.0,
+            ),
+            Body::new(
+                VecTwo::new(5.0, 0.0),
+                VecTwo::new(-60.0, 0.0),
+                circle(5.0),
+                1.0,
+            ),
+        ];
+        step_bodies(&mut bodies, &[], &[], 1.0 / 60.0);
+
+        assert!(bodies[0].vel.x < 0.0);
+        assert!(bodies[1].vel.x > 0.0);
+    }
+}
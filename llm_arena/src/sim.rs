@@ -0,0 +1,675 @@
+use crate::{level::*, physics::*, rng::*};
+use elara_engine::vectors::*;
+use serde::{Deserialize, Serialize};
+
+pub mod behavior;
+pub mod headless;
+pub mod replay;
+pub mod rules;
+
+pub use behavior::*;
+pub use headless::*;
+pub use replay::*;
+pub use rules::*;
+
+/// Seconds per simulation tick. The sim always steps by this regardless of frame rate.
+pub const SIM_TIMESTEP: f64 = 1.0 / 60.0;
+
+/// Most ticks to run in a single frame, so a long frame doesn't stall the game
+pub const SIM_MAX_STEPS_PER_FRAME: i32 = 10;
+
+/// Seconds before a wandering unit picks a new direction
+const WANDER_INTERVAL: f64 = 1.0;
+
+/// How bouncy units are when they hit each other or the level
+const UNIT_RESTITUTION: f64 = 0.2;
+
+/// How quickly units reach the velocity their behavior wants. Fraction per second.
+const STEER_RATE: f64 = 20.0;
+
+/// Ticks before a unit following a path looks for a new one
+const REPATH_TICKS: u32 = 20;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TeamStats {
+    pub health: f64,
+
+    /// Units per second
+    pub speed: f64,
+
+    /// Damage per hit
+    pub attack: f64,
+
+    /// Distance between unit edges needed to hit
+    pub attack_range: f64,
+
+    /// Seconds between hits
+    pub attack_cooldown: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimConfig {
+    pub squares: TeamStats,
+    pub circles: TeamStats,
+
+    /// Match ends in a draw after this many ticks. Matches with a longer time limit
+    /// in their rules run until the time limit instead.
+    pub max_ticks: u64,
+}
+
+impl SimConfig {
+    pub fn stats(&self, team: EntityKind) -> &TeamStats {
+        match team {
+            EntityKind::Square => &self.squares,
+            EntityKind::Circle => &self.circles,
+        }
+    }
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            squares: TeamStats {
+                health: 100.0,
+                speed: 60.0,
+                attack: 12.0,
+                attack_range: 5.0,
+                attack_cooldown: 0.8,
+            },
+            circles: TeamStats {
+                health: 80.0,
+                speed: 80.0,
+                attack: 10.0,
+                attack_range: 5.0,
+                attack_cooldown: 0.6,
+            },
+            max_ticks: 60 * 120,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Unit {
+    /// Spawn order. Stays the same as other units die.
+    pub id: u32,
+
+    pub team: EntityKind,
+    pub pos: VecTwo,
+    pub vel: VecTwo,
+    pub rotation: f64,
+    pub radius: f64,
+
+    pub health: f64,
+
+    /// Seconds until the unit can attack again
+    pub cooldown: f64,
+
+    /// Spawn position. Patrols circle around this.
+    pub home: VecTwo,
+    pub patrol_angle: f64,
+    pub wander_angle: f64,
+    pub wander_timer: f64,
+
+    /// Waypoints around level geometry, used when the target isn't in a straight line
+    pub path: Vec<VecTwo>,
+    pub path_age: u32,
+}
+
+impl Unit {
+    pub fn alive(&self) -> bool {
+        self.health > 0.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SimOutcome {
+    Winner(EntityKind),
+    Draw,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sim {
+    pub units: Vec<Unit>,
+    pub tick: u64,
+    pub outcome: Option<SimOutcome>,
+    pub rules: MatchRules,
+
+    pub square_kills: u32,
+    pub circle_kills: u32,
+
+    pub seed: u64,
+
+    /// Everything notable that happened, for replays
+    pub events: Vec<ReplayEvent>,
+
+    /// state_hash after each tick. Index 0 is after the first tick.
+    pub tick_hashes: Vec<u64>,
+
+    config: SimConfig,
+    behaviors: TeamBehaviors,
+    walls: Vec<Wall>,
+    obstacles: Vec<Obstacle>,
+
+    /// None when the level has no geometry to path around
+    nav: Option<NavGrid>,
+
+    rng: Rng,
+}
+
+impl Sim {
+    pub fn new(level: &Level, mut config: SimConfig, seed: u64) -> Self {
+        let rules = compile_rules(&level.rules, level);
+
+        // the draw cap can't cut a match short of its time limit, "squares survive 300 seconds"
+        if let Some(limit) = rules.time_limit {
+            config.max_ticks = config
+                .max_ticks
+                .max((limit / SIM_TIMESTEP).ceil() as u64 + 1);
+        }
+
+        let mut units: Vec<Unit> = vec![];
+
+        for team in [EntityKind::Square, EntityKind::Circle] {
+            let stats = config.stats(team);
+            for entity in level.entities(team) {
+                units.push(Unit {
+                    id: units.len() as u32,
+                    team,
+                    pos: entity.pos,
+                    vel: VecTwo::new(0.0, 0.0),
+                    rotation: entity.rotation,
+                    radius: entity.size * 0.5,
+                    health: stats.health,
+                    cooldown: 0.0,
+
+                    home: entity.pos,
+                    patrol_angle: 0.0,
+                    wander_angle: 0.0,
+                    wander_timer: 0.0,
+
+                    path: vec![],
+                    path_age: 0,
+                });
+            }
+        }
+
+        let mut sim = Self {
+            units,
+            tick: 0,
+            outcome: None,
+            rules,
+
+            square_kills: 0,
+            circle_kills: 0,
+
+            seed,
+            events: vec![],
+            tick_hashes: vec![],
+
+            config,
+            behaviors: compile_behaviors(&level.behaviors).0,
+            walls: level.walls.clone(),
+            obstacles: level.obstacles.clone(),
+            nav: if level.walls.is_empty() && level.obstacles.is_empty() {
+                None
+            } else {
+                Some(NavGrid::build(level, ENTITY_SIZE * 0.5))
+            },
+            rng: Rng::new(seed),
+        };
+        sim.check_outcome();
+        sim
+    }
+
+    pub fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    pub fn kills(&self, team: EntityKind) -> u32 {
+        match team {
+            EntityKind::Square => self.square_kills,
+            EntityKind::Circle => self.circle_kills,
+        }
+    }
+
+    /// Seconds since the match started
+    pub fn time(&self) -> f64 {
+        self.tick as f64 * SIM_TIMESTEP
+    }
+
+    /// Seconds left before the time limit. None if there is no limit.
+    pub fn time_left(&self) -> Option<f64> {
+        self.rules
+            .time_limit
+            .map(|limit| f64::max(limit - self.time(), 0.0))
+    }
+
+    pub fn alive_count(&self, team: EntityKind) -> usize {
+        self.units
+            .iter()
+            .filter(|u| u.team == team && u.alive())
+            .count()
+    }
+
+    /// Advance one fixed tick
+    pub fn step(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+
+        let targets: Vec<Option<usize>> = (0..self.units.len())
+            .map(|i| self.nearest(i, true))
+            .collect();
+
+        // move by behavior. Everyone moves from the same snapshot so unit order doesn't matter.
+        let mut next_positions: Vec<VecTwo> = vec![];
+        for i in 0..self.units.len() {
+            let action = self.pick_action(i);
+            next_positions.push(self.run_action(i, action));
+        }
+
+        // steer toward where the behavior wants to be, then let physics resolve contacts
+        let steer = f64::min(STEER_RATE * SIM_TIMESTEP, 1.0);
+        let mut bodies: Vec<Body> = vec![];
+        for (unit, target) in self.units.iter().zip(next_positions) {
+            let desired_x = (target.x - unit.pos.x) / SIM_TIMESTEP;
+            let desired_y = (target.y - unit.pos.y) / SIM_TIMESTEP;
+            let vel = VecTwo::new(
+                unit.vel.x + (desired_x - unit.vel.x) * steer,
+                unit.vel.y + (desired_y - unit.vel.y) * steer,
+            );
+
+            bodies.push(Body::new(
+                unit.pos,
+                vel,
+                Collider::for_entity(unit.team, unit.radius * 2.0, unit.rotation),
+                UNIT_RESTITUTION,
+            ));
+        }
+
+        step_bodies(&mut bodies, &self.walls, &self.obstacles, SIM_TIMESTEP);
+
+        for (unit, body) in self.units.iter_mut().zip(bodies) {
+            unit.pos = body.pos;
+            unit.vel = body.vel;
+        }
+
+        // attacks are collected then applied so both sides of a trade land on the same tick
+        let mut damage: Vec<f64> = vec![0.0; self.units.len()];
+        for i in 0..self.units.len() {
+            let unit = &mut self.units[i];
+            unit.cooldown = f64::max(unit.cooldown - SIM_TIMESTEP, 0.0);
+
+            if !unit.alive() || unit.cooldown > 0.0 {
+                continue;
+            }
+
+            let Some(target) = targets[i] else {
+                continue;
+            };
+
+            let stats = self.config.stats(self.units[i].team).clone();
+            let gap = distance(self.units[i].pos, self.units[target].pos)
+                - self.units[i].radius
+                - self.units[target].radius;
+
+            if gap <= stats.attack_range {
+                damage[target] += stats.attack;
+                self.units[i].cooldown = stats.attack_cooldown;
+
+                self.events.push(ReplayEvent::Attack {
+                    tick: self.tick + 1,
+                    unit: self.units[i].id,
+                    target: self.units[target].id,
+                });
+            }
+        }
+
+        for (unit, damage) in self.units.iter_mut().zip(damage) {
+            if damage <= 0.0 || !unit.alive() {
+                continue;
+            }
+
+            unit.health -= damage;
+
+            // only enemies deal damage, so the kill goes to the other team
+            if !unit.alive() {
+                match unit.team.other() {
+                    EntityKind::Square => self.square_kills += 1,
+                    EntityKind::Circle => self.circle_kills += 1,
+                }
+
+                self.events.push(ReplayEvent::Death {
+                    tick: self.tick + 1,
+                    unit: unit.id,
+                    team: unit.team,
+                });
+            }
+        }
+
+        self.units.retain(|u| u.alive());
+        self.tick += 1;
+        self.check_outcome();
+
+        self.tick_hashes.push(self.state_hash());
+    }
+
+    /// FNV-1a over every unit's exact position, velocity and health, plus the score.
+    /// Any drift in a replayed match changes it on the tick the drift happens.
+    pub fn state_hash(&self) -> u64 {
+        let mut hash: u64 = 0xCBF29CE484222325;
+        let mut add = |v: u64| {
+            for b in v.to_le_bytes() {
+                hash ^= b as u64;
+                hash = hash.wrapping_mul(0x100000001B3);
+            }
+        };
+
+        add(self.tick);
+        add(self.square_kills as u64);
+        add(self.circle_kills as u64);
+        for unit in &self.units {
+            add(unit.id as u64);
+            add(unit.pos.x.to_bits());
+            add(unit.pos.y.to_bits());
+            add(unit.vel.x.to_bits());
+            add(unit.vel.y.to_bits());
+            add(unit.health.to_bits());
+            add(unit.cooldown.to_bits());
+        }
+
+        hash
+    }
+
+    /// Nearest living unit on the other team, or on the same team when enemy is false
+    fn nearest(&self, index: usize, enemy: bool) -> Option<usize> {
+        let unit = &self.units[index];
+
+        let mut best: Option<(usize, f64)> = None;
+        for (i, other) in self.units.iter().enumerate() {
+            if i == index || (other.team != unit.team) != enemy || !other.alive() {
+                continue;
+            }
+
+            let dist = distance(unit.pos, other.pos);
+            match best {
+                Some((_, best_dist)) if best_dist <= dist => {}
+                _ => best = Some((i, dist)),
+            }
+        }
+
+        best.map(|(i, _)| i)
+    }
+
+    /// First rule whose condition passes. Units with no passing rule hold still.
+    fn pick_action(&self, index: usize) -> Action {
+        let unit = &self.units[index];
+        let stats = self.config.stats(unit.team);
+
+        for rule in self.behaviors.rules(unit.team) {
+            let pass = match rule.condition {
+                Condition::Always => true,
+                Condition::HealthBelow(fraction) => unit.health < stats.health * fraction,
+                Condition::EnemyWithin(dist) => match self.nearest(index, true) {
+                    Some(enemy) => distance(unit.pos, self.units[enemy].pos) <= dist,
+                    None => false,
+                },
+            };
+
+            if pass {
+                return rule.action;
+            }
+        }
+
+        Action::Hold
+    }
+
+    /// Position and stop distance for a target. None if there is nothing to target.
+    fn target_pos(&self, index: usize, target: Target) -> Option<(VecTwo, f64)> {
+        let unit = &self.units[index];
+
+        match target {
+            Target::NearestEnemy => self.nearest(index, true).map(|i| {
+                let other = &self.units[i];
+                let range = self.config.stats(unit.team).attack_range;
+                (other.pos, unit.radius + other.radius + range)
+            }),
+            Target::NearestAlly => self.nearest(index, false).map(|i| {
+                let other = &self.units[i];
+                (other.pos, unit.radius + other.radius)
+            }),
+            Target::Center => Some((VecTwo::new(0.0, 0.0), 0.0)),
+        }
+    }
+
+    /// Where the unit ends up this tick after running the action
+    fn run_action(&mut self, index: usize, action: Action) -> VecTwo {
+        let unit = self.units[index].clone();
+        let step = self.config.stats(unit.team).speed * SIM_TIMESTEP;
+
+        match action {
+            Action::Hold => unit.pos,
+
+            Action::Chase(target) => match self.target_pos(index, target) {
+                Some((target_pos, stop)) => {
+                    let gap = distance(unit.pos, target_pos) - stop;
+                    if gap > 0.0 {
+                        self.move_toward(index, target_pos, f64::min(step, gap))
+                    } else {
+                        unit.pos
+                    }
+                }
+                None => unit.pos,
+            },
+
+            Action::Flee(target) => match self.target_pos(index, target) {
+                Some((target_pos, _)) => {
+                    let away = VecTwo::new(
+                        unit.pos.x + (unit.pos.x - target_pos.x),
+                        unit.pos.y + (unit.pos.y - target_pos.y),
+                    );
+                    self.move_toward(index, away, step)
+                }
+                None => unit.pos,
+            },
+
+            Action::Patrol { radius } => {
+                let angle = unit.patrol_angle + (step / radius);
+                self.units[index].patrol_angle = angle;
+
+                let point = VecTwo::new(
+                    unit.home.x + f64::cos(angle) * radius,
+                    unit.home.y + f64::sin(angle) * radius,
+                );
+                let dist = distance(unit.pos, point);
+                self.move_toward(index, point, f64::min(step, dist))
+            }
+
+            Action::Wander => {
+                let mut angle = unit.wander_angle;
+                let mut timer = unit.wander_timer - SIM_TIMESTEP;
+                if timer <= 0.0 {
+                    angle = self.rng.next_f64() * 2.0 * std::f64::consts::PI;
+                    timer = WANDER_INTERVAL;
+                }
+                self.units[index].wander_angle = angle;
+                self.units[index].wander_timer = timer;
+
+                let point = VecTwo::new(
+                    unit.pos.x + f64::cos(angle) * step,
+                    unit.pos.y + f64::sin(angle) * step,
+                );
+                self.move_toward(index, point, step)
+            }
+        }
+    }
+
+    /// Point step along the way toward the target. Goes straight when it can, otherwise follows
+    /// a nav grid path. Physics stops it short of anything solid.
+    fn move_toward(&mut self, index: usize, target: VecTwo, step: f64) -> VecTwo {
+        let pos = self.units[index].pos;
+        let mut goal = target;
+
+        if let Some(nav) = &self.nav {
+            let unit = &mut self.units[index];
+
+            if nav.line_clear(pos, target) {
+                unit.path.clear();
+            } else {
+                unit.path_age += 1;
+                if unit.path.is_empty() || unit.path_age >= REPATH_TICKS {
+                    unit.path = nav.find_path(pos, target).unwrap_or_default();
+                    unit.path_age = 0;
+                }
+
+                // drop waypoints already reached
+                while unit.path.len() > 1 && distance(pos, unit.path[0]) < NAV_CELL_SIZE {
+                    unit.path.remove(0);
+                }
+
+                if let Some(waypoint) = unit.path.first() {
+                    goal = *waypoint;
+                }
+            }
+        }
+
+        let dx = goal.x - pos.x;
+        let dy = goal.y - pos.y;
+        let len = f64::sqrt(dx * dx + dy * dy);
+        if len <= 0.0 {
+            return pos;
+        }
+
+        VecTwo::new(pos.x + (dx / len) * step, pos.y + (dy / len) * step)
+    }
+
+    fn check_outcome(&mut self) {
+        let squares = self.alive_count(EntityKind::Square);
+        let circles = self.alive_count(EntityKind::Circle);
+        let time_up = matches!(self.time_left(), Some(left) if left <= 0.0);
+
+        let mut outcome = match self.rules.objective {
+            Objective::Eliminate => None,
+            Objective::Survive(team) => {
+                if self.alive_count(team) == 0 {
+                    Some(SimOutcome::Winner(team.other()))
+                } else if time_up {
+                    Some(SimOutcome::Winner(team))
+                } else {
+                    None
+                }
+            }
+            Objective::Kills(target) => {
+                let square_done = self.square_kills >= target;
+                let circle_done = self.circle_kills >= target;
+                match (square_done, circle_done) {
+                    (true, true) => Some(self.outcome_by_score()),
+                    (true, false) => Some(SimOutcome::Winner(EntityKind::Square)),
+                    (false, true) => Some(SimOutcome::Winner(EntityKind::Circle)),
+                    (false, false) => None,
+                }
+            }
+        };
+
+        // a wiped out team ends any match
+        if outcome.is_none() {
+            outcome = match (squares, circles) {
+                (0, 0) => Some(SimOutcome::Draw),
+                (_, 0) => Some(SimOutcome::Winner(EntityKind::Square)),
+                (0, _) => Some(SimOutcome::Winner(EntityKind::Circle)),
+                _ => None,
+            };
+        }
+
+        if outcome.is_none() && time_up {
+            outcome = Some(self.outcome_by_score());
+        }
+
+        if outcome.is_none() && self.tick >= self.config.max_ticks {
+            outcome = Some(SimOutcome::Draw);
+        }
+
+        if let Some(outcome) = outcome {
+            self.events.push(ReplayEvent::End {
+                tick: self.tick,
+                outcome,
+            });
+        }
+
+        self.outcome = outcome;
+    }
+
+    /// Team with more kills wins
+    fn outcome_by_score(&self) -> SimOutcome {
+        match self.square_kills.cmp(&self.circle_kills) {
+            std::cmp::Ordering::Greater => SimOutcome::Winner(EntityKind::Square),
+            std::cmp::Ordering::Less => SimOutcome::Winner(EntityKind::Circle),
+            std::cmp::Ordering::Equal => SimOutcome::Draw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_level_gen::RulesGen;
+
+    /// Units that can't hurt each other, so only the clock ends the match
+    fn harmless_config() -> SimConfig {
+        let mut config = SimConfig::default();
+        config.squares.attack = 0.0;
+        config.circles.attack = 0.0;
+        config
+    }
+
+    fn survive_level(time_limit: f64) -> Level {
+        let mut level = Level::new();
+        level.squares.push(Entity::new(VecTwo::new(-100.0, 0.0)));
+        level.circles.push(Entity::new(VecTwo::new(100.0, 0.0)));
+        level.rules = Some(RulesGen {
+            objective: "survive".to_string(),
+            team: "squares".to_string(),
+            kill_target: 0,
+            time_limit,
+        });
+        level
+    }
+
+    fn run(sim: &mut Sim) {
+        while !sim.is_finished() {
+            sim.step();
+        }
+    }
+
+    #[test]
+    fn survive_past_the_default_tick_cap() {
+        let config = harmless_config();
+        let default_seconds = config.max_ticks as f64 * SIM_TIMESTEP;
+        let limit = 300.0;
+        assert!(limit > default_seconds);
+
+        let mut sim = Sim::new(&survive_level(limit), config, 1);
+        run(&mut sim);
+
+        assert_eq!(sim.outcome, Some(SimOutcome::Winner(EntityKind::Square)));
+        assert!(sim.time() >= limit);
+    }
+
+    #[test]
+    fn tick_cap_still_ends_matches_without_a_limit() {
+        let mut level = survive_level(0.0);
+        level.rules = None;
+        let config = harmless_config();
+        let max_ticks = config.max_ticks;
+
+        let mut sim = Sim::new(&level, config, 1);
+        run(&mut sim);
+
+        assert_eq!(sim.outcome, Some(SimOutcome::Draw));
+        assert_eq!(sim.tick, max_ticks);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{ai_level_gen::*, level::*};
+
+/// Most rules a single team can have
+pub const MAX_BEHAVIOR_RULES: usize = 8;
+
+/// Largest distance value a rule can use
+pub const MAX_BEHAVIOR_DISTANCE: f64 = GEN_RANGE * 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    Always,
+
+    /// Fraction of max health
+    HealthBelow(f64),
+
+    /// Any enemy center within this distance
+    EnemyWithin(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    NearestEnemy,
+    NearestAlly,
+    Center,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Chase(Target),
+    Flee(Target),
+    Patrol { radius: f64 },
+    Hold,
+    Wander,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BehaviorRule {
+    pub condition: Condition,
+    pub action: Action,
+}
+
+#[derive(Clone, Debug)]
+pub struct TeamBehaviors {
+    pub squares: Vec<BehaviorRule>,
+    pub circles: Vec<BehaviorRule>,
+}
+
+impl TeamBehaviors {
+    pub fn rules(&self, team: EntityKind) -> &Vec<BehaviorRule> {
+        match team {
+            EntityKind::Square => &self.squares,
+            EntityKind::Circle => &self.circles,
+        }
+    }
+
+    fn rules_mut(&mut self, team: EntityKind) -> &mut Vec<BehaviorRule> {
+        match team {
+            EntityKind::Square => &mut self.squares,
+            EntityKind::Circle => &mut self.circles,
+        }
+    }
+}
+
+impl Default for TeamBehaviors {
+    /// Everyone chases the nearest enemy
+    fn default() -> Self {
+        let chase = BehaviorRule {
+            condition: Condition::Always,
+            action: Action::Chase(Target::NearestEnemy),
+        };
+
+        Self {
+            squares: vec![chase],
+            circles: vec![chase],
+        }
+    }
+}
+
+pub fn parse_team(team: &str) -> Result<EntityKind, String> {
+    match team.trim().to_lowercase().as_str() {
+        "squares" | "square" => Ok(EntityKind::Square),
+        "circles" | "circle" => Ok(EntityKind::Circle),
+        _ => Err(format!("Unknown team '{}'", team)),
+    }
+}
+
+/// Check one rule against the allowed vocabulary and value ranges
+pub fn compile_rule(rule: &BehaviorRuleGen) -> Result<BehaviorRule, String> {
+    let condition = match rule.condition.trim() {
+        "always" => Condition::Always,
+        "health_below" => {
+            if !(0.0..=1.0).contains(&rule.condition_value) {
+                return Err(format!(
+                    "health_below needs a value from 0 to 1, got {}",
+                    rule.condition_value
+                ));
+            }
+            Condition::HealthBelow(rule.condition_value)
+        }
+        "enemy_within" => {
+            Condition::EnemyWithin(check_distance("enemy_within", rule.condition_value)?)
+        }
+        other => return Err(format!("Unknown condition '{}'", other)),
+    };
+
+    let action = match rule.action.trim() {
+        "chase" => Action::Chase(parse_target(&rule.target)?),
+        "flee" => Action::Flee(parse_target(&rule.target)?),
+        "patrol" => Action::Patrol {
+            radius: check_distance("patrol", rule.action_value)?,
+        },
+        "hold" => Action::Hold,
+        "wander" => Action::Wander,
+        other => return Err(format!("Unknown action '{}'", other)),
+    };
+
+    Ok(BehaviorRule { condition, action })
+}
+
+/// Remove teams and rules that don't compile. Returns a message for each thing removed.
+pub fn sanitize_behaviors(behaviors: &mut Vec<BehaviorGen>) -> Vec<String> {
+    let mut errors: Vec<String> = vec![];
+    let mut seen: Vec<EntityKind> = vec![];
+
+    behaviors.retain_mut(|behavior| {
+        let team = match parse_team(&behavior.team) {
+            Ok(team) => team,
+            Err(error) => {
+                errors.push(error);
+                return false;
+            }
+        };
+
+        if seen.contains(&team) {
+            errors.push(format!("Duplicate behavior for team '{}'", behavior.team));
+            return false;
+        }
+        seen.push(team);
+
+        if behavior.rules.len() > MAX_BEHAVIOR_RULES {
+            errors.push(format!(
+                "{} has {} rules, over the limit of {}",
+                behavior.team,
+                behavior.rules.len(),
+                MAX_BEHAVIOR_RULES
+            ));
+            behavior.rules.truncate(MAX_BEHAVIOR_RULES);
+        }
+
+        let mut i = 0;
+        behavior.rules.retain(|rule| {
+            let ret = match compile_rule(rule) {
+                Ok(_) => true,
+                Err(error) => {
+                    errors.push(format!("{} rule {}. {}", behavior.team, i, error));
+                    false
+                }
+            };
+            i += 1;
+            ret
+        });
+
+        !behavior.rules.is_empty()
+    });
+
+    errors
+}
+
+/// Build the runtime behaviors. Anything that doesn't compile is skipped and reported.
+/// Teams with no valid rules keep the default chase.
+pub fn compile_behaviors(behaviors: &[BehaviorGen]) -> (TeamBehaviors, Vec<String>) {
+    let mut behaviors = behaviors.to_vec();
+    let errors = sanitize_behaviors(&mut behaviors);
+
+    let mut ret = TeamBehaviors::default();
+    for behavior in &behaviors {
+        // sanitize leaves only valid teams and rules
+        let team = parse_team(&behavior.team).unwrap();
+        *ret.rules_mut(team) = behavior
+            .rules
+            .iter()
+            .map(|rule| compile_rule(rule).unwrap())
+            .collect();
+    }
+
+    (ret, errors)
+}
+
+fn parse_target(target: &str) -> Result<Target, String> {
+    match target.trim() {
+        "nearest_enemy" => Ok(Target::NearestEnemy),
+        "nearest_ally" => Ok(Target::NearestAlly),
+        "center" => Ok(Target::Center),
+        other => Err(format!("Unknown target '{}'", other)),
+    }
+}
+
+fn check_distance(name: &str, value: f64) -> Result<f64, String> {
+    if !value.is_finite() || value <= 0.0 || value > MAX_BEHAVIOR_DISTANCE {
+        return Err(format!(
+            "{} needs a distance above 0 and at most {}, got {}",
+            name, MAX_BEHAVIOR_DISTANCE, value
+        ));
+    }
+    Ok(value)
+}
@@ -0,0 +1,158 @@
+use crate::{ai_level_gen::*, level::*, rng::*, sim::*};
+
+#[derive(Clone, Debug)]
+pub struct BatchConfig {
+    pub matches: u32,
+
+    /// Match i runs with a seed derived from this, so a batch is reproducible
+    pub base_seed: u64,
+
+    pub sim: SimConfig,
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchResult {
+    pub seed: u64,
+    pub outcome: SimOutcome,
+    pub ticks: u64,
+    pub squares_alive: usize,
+    pub circles_alive: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BatchReport {
+    pub matches: u32,
+
+    pub square_wins: u32,
+    pub circle_wins: u32,
+    pub draws: u32,
+
+    pub mean_ticks: f64,
+    pub min_ticks: u64,
+    pub max_ticks: u64,
+
+    /// Average survivors of the winning team, over matches that team won
+    pub mean_square_survivors: f64,
+    pub mean_circle_survivors: f64,
+}
+
+impl BatchReport {
+    pub fn from_results(results: &[MatchResult]) -> Self {
+        let mut report = BatchReport {
+            matches: results.len() as u32,
+            min_ticks: u64::MAX,
+            ..Default::default()
+        };
+
+        if results.is_empty() {
+            report.min_ticks = 0;
+            return report;
+        }
+
+        let mut total_ticks: u64 = 0;
+        let mut square_survivors: usize = 0;
+        let mut circle_survivors: usize = 0;
+
+        for result in results {
+            match result.outcome {
+                SimOutcome::Winner(EntityKind::Square) => {
+                    report.square_wins += 1;
+                    square_survivors += result.squares_alive;
+                }
+                SimOutcome::Winner(EntityKind::Circle) => {
+                    report.circle_wins += 1;
+                    circle_survivors += result.circles_alive;
+                }
+                SimOutcome::Draw => report.draws += 1,
+            }
+
+            total_ticks += result.ticks;
+            report.min_ticks = report.min_ticks.min(result.ticks);
+            report.max_ticks = report.max_ticks.max(result.ticks);
+        }
+
+        report.mean_ticks = total_ticks as f64 / results.len() as f64;
+        if report.square_wins > 0 {
+            report.mean_square_survivors = square_survivors as f64 / report.square_wins as f64;
+        }
+        if report.circle_wins > 0 {
+            report.mean_circle_survivors = circle_survivors as f64 / report.circle_wins as f64;
+        }
+
+        report
+    }
+
+    /// 0..1
+    pub fn win_rate(&self, team: EntityKind) -> f64 {
+        if self.matches == 0 {
+            return 0.0;
+        }
+
+        let wins = match team {
+            EntityKind::Square => self.square_wins,
+            EntityKind::Circle => self.circle_wins,
+        };
+        wins as f64 / self.matches as f64
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} matches. Squares {:.1}% Circles {:.1}% Draws {}. Length {:.1}s avg ({:.1}s - {:.1}s). Survivors sq {:.1} ci {:.1}",
+            self.matches,
+            self.win_rate(EntityKind::Square) * 100.0,
+            self.win_rate(EntityKind::Circle) * 100.0,
+            self.draws,
+            self.mean_ticks * SIM_TIMESTEP,
+            self.min_ticks as f64 * SIM_TIMESTEP,
+            self.max_ticks as f64 * SIM_TIMESTEP,
+            self.mean_square_survivors,
+            self.mean_circle_survivors,
+        )
+    }
+}
+
+/// Run one match to completion without rendering
+pub fn run_match(level: &Level, config: &SimConfig, seed: u64) -> MatchResult {
+    let mut sim = Sim::new(level, config.clone(), seed);
+    while !sim.is_finished() {
+        sim.step();
+    }
+
+    MatchResult {
+        seed,
+        outcome: sim.outcome.unwrap(),
+        ticks: sim.tick,
+        squares_alive: sim.alive_count(EntityKind::Square),
+        circles_alive: sim.alive_count(EntityKind::Circle),
+    }
+}
+
+/// Run many matches on the same placed level
+pub fn run_batch(level: &Level, batch: &BatchConfig) -> (BatchReport, Vec<MatchResult>) {
+    let mut seeds = Rng::new(batch.base_seed);
+    let results: Vec<MatchResult> = (0..batch.matches)
+        .map(|_| run_match(level, &batch.sim, seeds.next_u64()))
+        .collect();
+
+    (BatchReport::from_results(&results), results)
+}
+
+/// Run many matches on a generated layout, placing the entities again from each match seed.
+/// Measures the layout itself rather than one lucky placement.
+pub fn run_layout_batch(
+    response: &LevelGenResponse,
+    batch: &BatchConfig,
+) -> (BatchReport, Vec<MatchResult>) {
+    let mut seeds = Rng::new(batch.base_seed);
+    let results: Vec<MatchResult> = (0..batch.matches)
+        .map(|_| {
+            let seed = seeds.next_u64();
+
+            let mut placement = Rng::new(seed);
+            let level = Level::from_response(response, &mut || placement.next_f64());
+            run_match(&level, &batch.sim, seed)
+        })
+        .collect();
+
+    (BatchReport::from_results(&results), results)
+}
@@ -0,0 +1,206 @@
+use crate::{
+    ai_level_gen::{ValidationLimits, ValidationReport, validate_level},
+    level::*,
+    sim::*,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Bump when the replay layout changes. Older versions must still load.
+/// 2 added attack events and per tick state hashes
+pub const REPLAY_FILE_VERSION: u32 = 2;
+
+/// Ticks between saved sim states while playing back. Lower scrubs faster and uses more memory.
+const KEYFRAME_INTERVAL: u64 = 120;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    Attack {
+        tick: u64,
+        unit: u32,
+        target: u32,
+    },
+    Death {
+        tick: u64,
+        unit: u32,
+        team: EntityKind,
+    },
+    End {
+        tick: u64,
+        outcome: SimOutcome,
+    },
+}
+
+/// A recorded match. The sim is deterministic, so level + config + seed replays it exactly.
+/// Events and state hashes are kept to check playback against, tick by tick.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub level: LevelFile,
+    pub config: SimConfig,
+    pub seed: u64,
+    pub ticks: u64,
+    pub events: Vec<ReplayEvent>,
+
+    /// Sim state hash after every tick. Empty for version 1 replays, which only check events.
+    #[serde(default)]
+    pub tick_hashes: Vec<u64>,
+}
+
+impl Replay {
+    pub fn from_sim(level: &Level, source: &LevelSource, sim: &Sim) -> Self {
+        Self {
+            version: REPLAY_FILE_VERSION,
+            level: LevelFile::new(source, level),
+            config: sim.config().clone(),
+            seed: sim.seed,
+            ticks: sim.tick,
+            events: sim.events.clone(),
+            tick_hashes: sim.tick_hashes.clone(),
+        }
+    }
+}
+
+pub fn save_replay(path: &Path, replay: &Replay) -> Result<(), SaveError> {
+    let json = serde_json::to_string(replay)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// The recorded level goes through the same limits as load_level. A level that had to be
+/// fixed plays back differently, which shows up as a desync.
+pub fn load_replay(
+    path: &Path,
+    limits: &ValidationLimits,
+) -> Result<(Replay, ValidationReport), SaveError> {
+    let json = std::fs::read_to_string(path)?;
+    let mut replay: Replay = serde_json::from_str(&json)?;
+
+    if replay.version > REPLAY_FILE_VERSION {
+        return Err(SaveError::UnsupportedVersion {
+            version: replay.version,
+        });
+    }
+
+    let mut level = replay.level.to_level();
+    let report = validate_level(&mut level, limits);
+    if report.rejected {
+        return Err(SaveError::Rejected {
+            issues: report.issues,
+        });
+    }
+    if !report.is_clean() {
+        replay.level = LevelFile::new(&replay.level.source, &level);
+    }
+
+    Ok((replay, report))
+}
+
+/// Plays a replay back by running the sim again. Keeps keyframes so scrubbing backward
+/// doesn't need to run from the start.
+#[derive(Debug)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    pub sim: Sim,
+
+    pub paused: bool,
+
+    /// Playback rate, 1 is real time
+    pub speed: f64,
+
+    /// First tick where the replayed sim stopped matching the recording. Playback pauses there.
+    pub desync: Option<u64>,
+
+    keyframes: Vec<Sim>,
+    accumulator: f64,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        let level = replay.level.to_level();
+        let sim = Sim::new(&level, replay.config.clone(), replay.seed);
+
+        Self {
+            replay,
+            keyframes: vec![sim.clone()],
+            sim,
+
+            paused: false,
+            speed: 1.0,
+            desync: None,
+
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.sim.tick >= self.replay.ticks || self.sim.is_finished()
+    }
+
+    /// Advance by frame time, scaled by speed
+    pub fn update(&mut self, delta_time: f64) {
+        if self.paused || self.at_end() {
+            self.accumulator = 0.0;
+            return;
+        }
+
+        self.accumulator += delta_time * self.speed;
+        while self.accumulator >= SIM_TIMESTEP && !self.at_end() {
+            self.step();
+            self.accumulator -= SIM_TIMESTEP;
+        }
+    }
+
+    /// Jump to an exact tick, clamped to the recording
+    pub fn seek(&mut self, tick: u64) {
+        let tick = tick.min(self.replay.ticks);
+
+        if tick < self.sim.tick {
+            let keyframe = ((tick / KEYFRAME_INTERVAL) as usize).min(self.keyframes.len() - 1);
+            self.sim = self.keyframes[keyframe].clone();
+        }
+
+        while self.sim.tick < tick && !self.sim.is_finished() {
+            self.step();
+        }
+        self.accumulator = 0.0;
+    }
+
+    fn step(&mut self) {
+        let events_before = self.sim.events.len();
+        self.sim.step();
+
+        if self.sim.tick % KEYFRAME_INTERVAL == 0
+            && self.sim.tick / KEYFRAME_INTERVAL == self.keyframes.len() as u64
+        {
+            self.keyframes.push(self.sim.clone());
+        }
+
+        if self.desync.is_none() && !self.matches_recording(events_before) {
+            self.desync = Some(self.sim.tick);
+            self.paused = true;
+        }
+    }
+
+    /// Does the tick just run match the recording. Only the new events are compared, earlier
+    /// ones were checked when they happened.
+    fn matches_recording(&self, events_before: usize) -> bool {
+        let tick = self.sim.tick as usize;
+        if let (Some(recorded), Some(played)) = (
+            self.replay.tick_hashes.get(tick.wrapping_sub(1)),
+            self.sim.tick_hashes.last(),
+        ) {
+            if recorded != played {
+                return false;
+            }
+        }
+
+        let new_events = &self.sim.events[events_before..];
+        if self.replay.events.get(events_before..self.sim.events.len()) != Some(new_events) {
+            return false;
+        }
+
+        // the recording ended here, so nothing should be left over
+        !self.at_end() || self.sim.events.len() == self.replay.events.len()
+    }
+}
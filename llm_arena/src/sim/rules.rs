@@ -0,0 +1,133 @@
+use crate::{ai_level_gen::*, level::*, sim::parse_team};
+
+/// Longest time limit a level can ask for, in seconds
+pub const MAX_TIME_LIMIT: f64 = 600.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Objective {
+    /// Last team standing wins
+    Eliminate,
+
+    /// Team wins if any of it is alive when time runs out
+    Survive(EntityKind),
+
+    /// First team to this many kills wins
+    Kills(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MatchRules {
+    pub objective: Objective,
+
+    /// Seconds. None runs until the objective is met or SimConfig::max_ticks.
+    pub time_limit: Option<f64>,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        Self {
+            objective: Objective::Eliminate,
+            time_limit: None,
+        }
+    }
+}
+
+impl MatchRules {
+    pub fn describe(&self) -> String {
+        let objective = match self.objective {
+            Objective::Eliminate => "Last team standing wins".to_string(),
+            Objective::Survive(team) => format!("{} win by surviving", team_name(team)),
+            Objective::Kills(kills) => format!("First team to {} kills wins", kills),
+        };
+
+        match self.time_limit {
+            Some(limit) => format!("{}. Time limit {:.0}s", objective, limit),
+            None => objective,
+        }
+    }
+}
+
+pub fn team_name(team: EntityKind) -> &'static str {
+    match team {
+        EntityKind::Square => "Squares",
+        EntityKind::Circle => "Circles",
+    }
+}
+
+/// Check the rules are well formed. Values that are only out of range are clamped in place
+/// and reported in issues. Returns Err when the rules can't be used at all.
+pub fn check_rules(
+    rules: &mut RulesGen,
+    square_count: i32,
+    circle_count: i32,
+    issues: &mut Vec<String>,
+) -> Result<MatchRules, String> {
+    if !rules.time_limit.is_finite() || rules.time_limit < 0.0 {
+        return Err(format!("Time limit of {} is not valid", rules.time_limit));
+    }
+
+    if rules.time_limit > MAX_TIME_LIMIT {
+        issues.push(format!(
+            "Time limit of {}s is over the limit of {}s",
+            rules.time_limit, MAX_TIME_LIMIT
+        ));
+        rules.time_limit = MAX_TIME_LIMIT;
+    }
+
+    let time_limit = if rules.time_limit > 0.0 {
+        Some(rules.time_limit)
+    } else {
+        None
+    };
+
+    let objective = match rules.objective.trim() {
+        "eliminate" => Objective::Eliminate,
+        "survive" => {
+            if time_limit.is_none() {
+                return Err("survive needs a time limit".to_string());
+            }
+            Objective::Survive(parse_team(&rules.team)?)
+        }
+        "kills" => {
+            if rules.kill_target < 1 {
+                return Err(format!(
+                    "kills needs a kill target of at least 1, got {}",
+                    rules.kill_target
+                ));
+            }
+
+            // can't need more kills than there are units to kill
+            let most = i32::max(square_count, circle_count).max(1);
+            if rules.kill_target > most {
+                issues.push(format!(
+                    "Kill target of {} can never be reached, lowered to {}",
+                    rules.kill_target, most
+                ));
+                rules.kill_target = most;
+            }
+            Objective::Kills(rules.kill_target as u32)
+        }
+        other => return Err(format!("Unknown objective '{}'", other)),
+    };
+
+    Ok(MatchRules {
+        objective,
+        time_limit,
+    })
+}
+
+/// Build runtime rules from already validated data. Bad rules fall back to the default.
+pub fn compile_rules(rules: &Option<RulesGen>, level: &Level) -> MatchRules {
+    let Some(rules) = rules else {
+        return MatchRules::default();
+    };
+
+    let mut rules = rules.clone();
+    check_rules(
+        &mut rules,
+        level.squares.len() as i32,
+        level.circles.len() as i32,
+        &mut vec![],
+    )
+    .unwrap_or_default()
+}
@@ -1,4 +1,10 @@
-use elara_engine::{render::image::Image, typeface::*, ui::*, vectors::*};
+use crate::{
+    ai_level_gen::{Aggregation, ClassifyConfig, GenerationMode, ValidationLimits},
+    editor::*,
+    level::*,
+    sim::*,
+};
+use elara_engine::{render::image::Image, typeface::*, ui::*};
 
 pub mod assets;
 
@@ -16,9 +22,47 @@ pub struct State {
     pub ui_context: Option<Context>,
 
     pub prompt: String,
+    pub validation_limits: ValidationLimits,
 
-    pub squares: Vec<VecTwo>,
-    pub circles: Vec<VecTwo>,
+    pub generation_mode: GenerationMode,
+
+    pub classify_config: ClassifyConfig,
+    pub template_path: String,
+
+    /// Classifications to run and combine. 1 is a single classification.
+    pub consistency_samples: usize,
+    pub aggregation: Aggregation,
+
+    /// Score each generated level with a second model
+    pub judge_enabled: bool,
+
+    /// Show recent span timings and trace events in the usage panel
+    pub debug_overlay: bool,
+
+    pub level: Level,
+    pub level_source: Option<LevelSource>,
+    pub level_code: Option<LevelCode>,
+
+    /// Reachability problems with the current level
+    pub level_warnings: Vec<String>,
+
+    pub history: History,
+    pub history_scroll: usize,
+
+    pub editor: Editor,
+
+    pub sim: Option<Sim>,
+    pub sim_config: SimConfig,
+    pub sim_accumulator: f64,
+
+    /// Level the current match started from, for saving replays
+    pub match_level: Level,
+
+    pub replay_player: Option<ReplayPlayer>,
+    pub replay_path: String,
+    pub replay_status: Option<String>,
+    pub level_path: String,
+    pub level_file_status: Option<String>,
 }
 
 impl State {
@@ -34,9 +78,41 @@ impl State {
             font_style_nav: Default::default(),
 
             prompt: String::new(),
+            validation_limits: ValidationLimits::default(),
+
+            generation_mode: GenerationMode::Classify,
+
+            classify_config: ClassifyConfig::default(),
+            template_path: "resources/prompts/classify_v1.txt".to_string(),
+
+            consistency_samples: 1,
+            aggregation: Aggregation::MajorityVote,
+
+            judge_enabled: false,
+            debug_overlay: false,
+
+            level: Level::new(),
+            level_source: None,
+            level_code: None,
+
+            level_warnings: vec![],
+
+            history: History::new(),
+            history_scroll: 0,
+
+            editor: Editor::new(),
+
+            sim: None,
+            sim_config: SimConfig::default(),
+            sim_accumulator: 0.0,
+
+            match_level: Level::new(),
 
-            squares: vec![],
-            circles: vec![],
+            replay_player: None,
+            replay_path: "replay.json".to_string(),
+            replay_status: None,
+            level_path: "level.json".to_string(),
+            level_file_status: None,
         }
     }
 }